@@ -1,9 +1,25 @@
 use bnf_rules_parser::{parse_rules, ParserGenerator, TokenParser};
-use proc_macro::TokenStream;
-use syn::parse_macro_input;
+use proc_macro::{Span, TokenStream};
+use syn::{parse_macro_input, Error};
 
 /// Generate LR(1) parser at compilation time.<br>
-/// If the specified grammar is ambiguous, compilation is aborted with conflict.
+/// If the specified grammar is ambiguous, compilation is aborted with conflict.<br>
+/// 'parse_source_with_errors' is also generated, which reports all errors and recovers from them.<br>
+/// '#[sync(..)]' declares terminal symbols where the recovery restarts parsing after skipping tokens.<br>
+/// 'error' in a pattern is an error production for the recovery, e.g. 'statement ::= error ";"'.<br>
+/// Shift/reduce conflicts can be resolved by '#[left(..)]', '#[right(..)]', '#[nonassoc(..)]' and '%prec'.<br>
+/// '#[entry(rule, ..)]' generates 'parse_rule' functions for other entry rules than 'source'.<br>
+/// '#[skip(..)]' adds tokens ignored by the lexer, '#[skip_default = false]' turns off skipping whitespaces.<br>
+/// '#[priority = N]' and '#[reserved(..)]' placed before a rule affect terminal symbols in the rule.<br>
+/// '#[table = pager]' or '#[table = lalr]' merges LR states to shrink the generated table.<br>
+/// '#[display = "name"]' placed before a rule names regexes and functions in the rule for syntax errors.<br>
+/// '#[mode(..)]', '#[push(mode)]' and '#[pop]' placed before a rule switch lexer modes by terminal symbols in the rule.<br>
+/// '#[layout = true]' makes 'INDENT', 'DEDENT' and 'NEWLINE' terminal symbols from line breaks and indentation.<br>
+/// '#[scan = on_demand]' scans each token only with terminal symbols acceptable in the parser state.<br>
+/// '#[typed_ast = true]' generates a struct or an enum for each rule in 'typed_ast', and 'parse_source_typed'.<br>
+/// 'pattern => { $1 + $3 }' is a semantic action, and '#[type = T]' placed before a rule declares the type of its values.
+/// Then 'parse_source_value' computes the value at each reduction, without building 'ASTNode'.<br>
+/// 'label:symbol' names a symbol, then 'node.child("label")' and 'node.children_named("label")' take the children.
 ///
 /// # Examples
 ///
@@ -36,8 +52,27 @@ pub fn bnf_rules(input: TokenStream) -> TokenStream {
     let token_parser = parse_macro_input!(input as TokenParser);
     let tokens = &token_parser.symbols;
 
-    let (map, generate_code) = parse_rules(tokens).unwrap();
+    let (map, settings) = match parse_rules(tokens) {
+        Ok(result) => result,
+        Err(error) => return error.to_compile_error().into(),
+    };
 
-    let mut generator = ParserGenerator::new(map);
-    return generator.generate(generate_code).unwrap().parse().unwrap();
+    let mut generator = ParserGenerator::new(map, settings);
+    let mut token_stream = match generator.generate() {
+        Ok(code) => match code.parse::<proc_macro2::TokenStream>() {
+            Ok(token_stream) => token_stream,
+            Err(error) => Error::new(
+                Span::call_site().into(),
+                format!("Internal error. Generated code is invalid. {}", error),
+            )
+            .to_compile_error(),
+        },
+        Err(error) => error.to_compile_error(),
+    };
+
+    for warning in generator.get_warnings().iter() {
+        token_stream.extend(warning.to_compile_warning());
+    }
+
+    return token_stream.into();
 }
@@ -0,0 +1,213 @@
+use crate::typed_ast::{OPTION_PATH, TERMINAL_NODE_PATH, VEC_PATH};
+use crate::values::get_symbols;
+use crate::{BNFRule, BNFRuleKind, BNFSymbol, ParserGenerator};
+use proc_macro2::{Group, Ident, Literal, Punct, Spacing, TokenStream, TokenTree};
+use syn::Error;
+
+/// Name of the module which has '__reduce' for semantic actions.
+pub const ACTION_MODULE_NAME: &str = "__bnf_rules_actions";
+
+impl ParserGenerator {
+    /// Whether any rule has a type or an action, then 'parse_{entry}_value' is generated.
+    pub(crate) fn has_actions(&self) -> bool {
+        return self.rule_map.values().any(|rule| {
+            rule.value_type.is_some() || rule.pattern_infos.iter().any(|info| info.action.is_some())
+        });
+    }
+
+    /// Code of the module which has '__reduce' running semantic actions.<br>
+    /// Values of unnamed rules are tuples, 'Option' for '[ ]' and 'Vec' for '{ }'.
+    pub(crate) fn action_code(&self) -> Result<String, Error> {
+        let mut code = format!("mod {} {{", ACTION_MODULE_NAME);
+        code += "use super::*;";
+        code += self
+            .reduce_code(
+                &|rule| self.action_value_type(rule),
+                &|rule, index, pattern| {
+                    return self.action_value_code(rule, index, pattern);
+                },
+            )?
+            .as_str();
+        code += "}";
+        return Ok(code);
+    }
+
+    /// Code of 'parse_{entry}_value', which parses the source into the value of the entry rule.
+    pub(crate) fn action_entry_code(&self, entry: &String, start_state: usize) -> String {
+        return self.value_entry_code(
+            format!("parse_{}_value", entry).as_str(),
+            ACTION_MODULE_NAME,
+            self.action_value_type(&self.rule_map[entry]).as_str(),
+            entry,
+            start_state,
+        );
+    }
+
+    /// Type of values of the rule, '()' if it is not declared by '#[type = ..]'.
+    fn action_value_type(&self, rule: &BNFRule) -> String {
+        return match rule.kind {
+            BNFRuleKind::Named => match &rule.value_type {
+                Some(value_type) => value_type.to_string(),
+                _ => "()".to_string(),
+            },
+            BNFRuleKind::Group if rule.or_patterns.len() > 1 => {
+                match self.get_common_symbol_type(rule) {
+                    Some(symbol_type) => symbol_type,
+                    _ => "()".to_string(),
+                }
+            }
+            BNFRuleKind::Group => match get_symbols(&rule.or_patterns[0]).as_slice() {
+                [symbol] => self.action_symbol_type(symbol),
+                symbols => {
+                    let types = symbols
+                        .iter()
+                        .map(|symbol| self.action_symbol_type(symbol))
+                        .collect::<Vec<_>>();
+                    format!("({})", types.join(", "))
+                }
+            },
+            BNFRuleKind::Optional => {
+                format!(
+                    "{}<{}>",
+                    OPTION_PATH,
+                    self.action_symbol_type(&rule.or_patterns[0][0])
+                )
+            }
+            BNFRuleKind::Repetition => {
+                format!(
+                    "{}<{}>",
+                    VEC_PATH,
+                    self.action_symbol_type(&rule.or_patterns[1][0])
+                )
+            }
+            BNFRuleKind::Start => String::new(),
+        };
+    }
+
+    fn action_symbol_type(&self, symbol: &BNFSymbol) -> String {
+        return match symbol {
+            BNFSymbol::NonTerminalSymbolName(name) => self.action_value_type(&self.rule_map[name]),
+            _ => TERMINAL_NODE_PATH.to_string(),
+        };
+    }
+
+    /// The type of a group like '( "+" | "-" )', whose patterns are one symbol of the same type.
+    fn get_common_symbol_type(&self, rule: &BNFRule) -> Option<String> {
+        let mut common_type = None;
+        for pattern in rule.or_patterns.iter() {
+            let symbol_type = match get_symbols(pattern).as_slice() {
+                [symbol] => self.action_symbol_type(symbol),
+                _ => return None,
+            };
+            match &common_type {
+                Some(common_type) if *common_type != symbol_type => return None,
+                _ => common_type = Some(symbol_type),
+            }
+        }
+        return common_type;
+    }
+
+    /// Code which computes the value of the pattern in '__reduce'.<br>
+    /// A pattern of a rule with a type requires an action, unless it is only one symbol.
+    fn action_value_code(
+        &self,
+        rule: &BNFRule,
+        index: usize,
+        pattern: &Vec<BNFSymbol>,
+    ) -> Result<String, Error> {
+        let values = (0..pattern.len())
+            .map(|i| format!("__v{}", i))
+            .collect::<Vec<_>>();
+
+        return Ok(match rule.kind {
+            BNFRuleKind::Named => {
+                let pattern_info = &rule.pattern_infos[index];
+                match &pattern_info.action {
+                    Some(action) => replace_symbol_values(action, pattern.len())?.to_string(),
+                    _ if rule.value_type.is_none() => "()".to_string(),
+                    _ if pattern.len() == 1 => "__v0".to_string(),
+                    _ => {
+                        return Err(Error::new(
+                            pattern_info.span,
+                            "An action is required for this pattern, because the rule has a type.",
+                        ))
+                    }
+                }
+            }
+            BNFRuleKind::Group if rule.or_patterns.len() > 1 => {
+                match self.get_common_symbol_type(rule) {
+                    Some(_) => "__v0".to_string(),
+                    _ => "()".to_string(),
+                }
+            }
+            BNFRuleKind::Group if values.len() == 1 => values[0].clone(),
+            BNFRuleKind::Group => format!("({})", values.join(", ")),
+            BNFRuleKind::Optional if values.is_empty() => format!("{}::None", OPTION_PATH),
+            BNFRuleKind::Optional => format!("{}::Some(__v0)", OPTION_PATH),
+            BNFRuleKind::Repetition if values.is_empty() => format!("{}::new()", VEC_PATH),
+            _ => "{ let mut values = __v1; values.insert(0, __v0); values }".to_string(),
+        });
+    }
+}
+
+/// Replace '$1', '$2', .. in the action with variables of the symbols.
+fn replace_symbol_values(action: &Group, symbol_count: usize) -> Result<Group, Error> {
+    let mut tokens = Vec::<TokenTree>::new();
+    let mut stream = action.stream().into_iter();
+
+    while let Some(token) = stream.next() {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == '$' => {
+                let literal = match stream.next() {
+                    Some(TokenTree::Literal(literal)) => literal.to_string(),
+                    _ => String::new(),
+                };
+                // '$1.0' is a float literal
+                let (number, field) = match literal.split_once('.') {
+                    Some((number, field)) => (number.to_string(), Some(field.to_string())),
+                    _ => (literal, None),
+                };
+
+                let index = match number.parse::<usize>() {
+                    Ok(index) if 1 <= index && index <= symbol_count => index,
+                    _ => {
+                        return Err(Error::new(
+                            punct.span(),
+                            format!(
+                                "Expected '$1' to '${}' for symbols of the pattern.",
+                                symbol_count
+                            ),
+                        ))
+                    }
+                };
+                tokens.push(TokenTree::Ident(Ident::new(
+                    format!("__v{}", index - 1).as_str(),
+                    punct.span(),
+                )));
+
+                if let Some(field) = field {
+                    let field = match field.parse::<usize>() {
+                        Ok(field) => field,
+                        _ => return Err(Error::new(punct.span(), "Invalid field.")),
+                    };
+                    tokens.push(TokenTree::Punct(Punct::new('.', Spacing::Alone)));
+                    tokens.push(TokenTree::Literal(Literal::usize_unsuffixed(field)));
+                }
+            }
+            TokenTree::Group(group) => {
+                tokens.push(TokenTree::Group(replace_symbol_values(
+                    group,
+                    symbol_count,
+                )?));
+            }
+            _ => tokens.push(token),
+        }
+    }
+
+    let mut group = Group::new(
+        action.delimiter(),
+        tokens.into_iter().collect::<TokenStream>(),
+    );
+    group.set_span(action.span());
+    return Ok(group);
+}
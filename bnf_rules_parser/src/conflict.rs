@@ -0,0 +1,407 @@
+use crate::symbol_set::SymbolSet;
+use crate::{
+    combine_errors, BNFSymbol, LRGroup, LRItem, Operation, ParserGenerator, ERROR_SYMBOL_NAME,
+};
+use proc_macro2::Span;
+use std::collections::{HashMap, VecDeque};
+use syn::Error;
+
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub group_number: usize,
+    pub symbol: BNFSymbol,
+    pub operations: (Operation, Operation),
+}
+
+/// A node of the item graph which is searched for derivations.<br>
+/// (group number, item index, lookahead symbol id)
+type ItemNode = (usize, usize, usize);
+
+/// An item which is partially recognized in a derivation.<br>
+/// (root name, pattern, current position)
+type DerivationFrame = (String, Vec<BNFSymbol>, usize);
+
+impl ParserGenerator {
+    /// Create an error for each conflict, which points to the pattern to be reduced.
+    pub(crate) fn create_conflicts_report(
+        &self,
+        lr_group_map: &HashMap<usize, LRGroup>,
+        conflicts: &Vec<Conflict>,
+    ) -> Error {
+        let mut errors = Vec::<Error>::new();
+
+        for conflict in conflicts.iter() {
+            let (message, span) = self.describe_conflict(lr_group_map, conflict);
+            errors.push(Error::new(span, message));
+        }
+
+        return combine_errors(errors).unwrap();
+    }
+
+    fn describe_conflict(
+        &self,
+        lr_group_map: &HashMap<usize, LRGroup>,
+        conflict: &Conflict,
+    ) -> (String, Span) {
+        let mut span = Span::call_site();
+        let (first_operation, second_operation) = &conflict.operations;
+        let mut message = format!(
+            "{:?} {:?} conflict! | Symbol : {} | State : {}",
+            first_operation,
+            second_operation,
+            display_symbol(&conflict.symbol),
+            conflict.group_number
+        );
+
+        let prefix = get_shortest_prefix(lr_group_map, self.entries.len(), conflict.group_number);
+        message += format!("\n  Prefix : {} •", display_symbols(&prefix)).as_str();
+
+        for operation in [first_operation, second_operation] {
+            let operation_name = match operation {
+                Operation::Shift(_) => "Shift",
+                Operation::Reduce(_) => "Reduce",
+                Operation::Accept => "Accept",
+                _ => "Unknown",
+            };
+
+            let group = lr_group_map.get(&conflict.group_number).unwrap();
+            let item_index = match self.find_conflict_item(group, &conflict.symbol, operation) {
+                Some(index) => index,
+                _ => continue,
+            };
+            let item = &group.item_list[item_index];
+            let rule = &self.single_pattern_rules[item.pattern_id];
+
+            if let Operation::Reduce(_) = operation {
+                span = rule.span;
+            }
+
+            message += format!(
+                "\n  {} item : {}",
+                operation_name,
+                display_item(&rule.root_symbol_name, &rule.pattern, item.current_position)
+            )
+            .as_str();
+
+            // a reduce item must be followed by the conflicted symbol
+            let lookahead = match operation {
+                Operation::Shift(_) => None,
+                _ => Some(&conflict.symbol),
+            };
+
+            let frames = match self.find_derivation(
+                lr_group_map,
+                conflict.group_number,
+                item_index,
+                lookahead,
+            ) {
+                Some(frames) => frames,
+                _ => continue,
+            };
+
+            message += format!(
+                "\n  {} derivation : {}",
+                operation_name,
+                display_example(&frames, lookahead)
+            )
+            .as_str();
+
+            let mut indent = "    ".to_string();
+            for (root_name, pattern, position) in frames.iter() {
+                if self.is_start_rule(root_name) {
+                    continue;
+                }
+                message += format!(
+                    "\n{}{}",
+                    indent,
+                    display_item(root_name, pattern, *position)
+                )
+                .as_str();
+                indent += "  ";
+            }
+        }
+
+        return (message, span);
+    }
+
+    fn find_conflict_item(
+        &self,
+        group: &LRGroup,
+        symbol: &BNFSymbol,
+        operation: &Operation,
+    ) -> Option<usize> {
+        let rules = &self.single_pattern_rules;
+        let symbol_id = self.symbol_id_map[symbol];
+
+        return group.item_list.iter().position(|item| match operation {
+            Operation::Shift(_) => item.get_next_symbol(rules) == Some(symbol),
+            Operation::Reduce(pattern_id) => {
+                item.is_last_position(rules)
+                    && item.first_set.contains(symbol_id)
+                    && item.pattern_id == *pattern_id
+            }
+            Operation::Accept => {
+                item.is_last_position(rules)
+                    && self.is_start_rule(&rules[item.pattern_id].root_symbol_name)
+            }
+            _ => false,
+        });
+    }
+
+    /// Search the shortest derivation from the start item to the specified item.<br>
+    /// If 'lookahead' is specified, the derivation must be followed by the symbol.
+    fn find_derivation(
+        &self,
+        lr_group_map: &HashMap<usize, LRGroup>,
+        target_group_number: usize,
+        target_item_index: usize,
+        lookahead: Option<&BNFSymbol>,
+    ) -> Option<Vec<DerivationFrame>> {
+        // (parent node, whether the edge enters a new pattern)
+        let rules = &self.single_pattern_rules;
+        let lookahead = lookahead.map(|symbol| self.symbol_id_map[symbol]);
+
+        let mut parent_map = HashMap::<ItemNode, Option<(ItemNode, bool)>>::new();
+        let mut queue = VecDeque::<ItemNode>::new();
+
+        for start_group_number in 0..self.entries.len() {
+            let start_group = lr_group_map.get(&start_group_number)?;
+            for (index, item) in start_group.item_list.iter().enumerate() {
+                let root_name = &rules[item.pattern_id].root_symbol_name;
+                if self.is_start_rule(root_name) && item.current_position == 0 {
+                    let node = (
+                        start_group_number,
+                        index,
+                        self.symbol_id_map[&BNFSymbol::EOF],
+                    );
+                    parent_map.insert(node, None);
+                    queue.push_back(node);
+                }
+            }
+        }
+
+        let mut target_node = Option::<ItemNode>::None;
+        while let Some(node) = queue.pop_front() {
+            let (group_number, item_index, item_lookahead) = &node;
+            if *group_number == target_group_number
+                && *item_index == target_item_index
+                && (lookahead.is_none() || lookahead == Some(*item_lookahead))
+            {
+                target_node = Some(node);
+                break;
+            }
+
+            let group = lr_group_map.get(group_number)?;
+            let item = &group.item_list[*item_index];
+            let next_symbol = match item.get_next_symbol(rules) {
+                Some(symbol) => symbol,
+                _ => continue,
+            };
+
+            let mut next_nodes = Vec::<(ItemNode, bool)>::new();
+
+            if let Some(next_group_number) = group.next_group_number_map.get(next_symbol) {
+                let next_group = lr_group_map.get(next_group_number)?;
+                if let Some(next_index) = find_advanced_item(next_group, item) {
+                    next_nodes.push(((*next_group_number, next_index, *item_lookahead), false));
+                }
+            }
+
+            if let BNFSymbol::NonTerminalSymbolName(name) = next_symbol {
+                // lookaheads of the child items when the item is followed by 'item_lookahead'
+                let mut item_first_set = SymbolSet::new(self.symbol_list.len());
+                item_first_set.insert(*item_lookahead);
+                let single_lookahead_item =
+                    LRItem::new(item.pattern_id, item.current_position, item_first_set);
+                let first_set = self.get_child_first_set(&single_lookahead_item);
+
+                for (index, group_item) in group.item_list.iter().enumerate() {
+                    if &rules[group_item.pattern_id].root_symbol_name != name
+                        || group_item.current_position != 0
+                    {
+                        continue;
+                    }
+                    for symbol_id in first_set.iter() {
+                        next_nodes.push(((*group_number, index, symbol_id), true));
+                    }
+                }
+            }
+
+            for (next_node, is_entering) in next_nodes {
+                if parent_map.contains_key(&next_node) {
+                    continue;
+                }
+                parent_map.insert(next_node, Some((node, is_entering)));
+                queue.push_back(next_node);
+            }
+        }
+
+        let mut edges = Vec::<(ItemNode, bool)>::new();
+        let mut node = target_node?;
+        let root_node = loop {
+            match parent_map.get(&node)? {
+                Some((parent, is_entering)) => {
+                    edges.push((node, *is_entering));
+                    node = *parent;
+                }
+                _ => break node,
+            }
+        };
+        edges.reverse();
+
+        let root_item = &lr_group_map.get(&root_node.0)?.item_list[root_node.1];
+        let root_rule = &rules[root_item.pattern_id];
+        let mut frames = vec![(
+            root_rule.root_symbol_name.clone(),
+            root_rule.pattern.clone(),
+            0,
+        )];
+
+        for ((group_number, item_index, _), is_entering) in edges {
+            if is_entering {
+                let item = &lr_group_map.get(&group_number)?.item_list[item_index];
+                let rule = &rules[item.pattern_id];
+                frames.push((rule.root_symbol_name.clone(), rule.pattern.clone(), 0));
+            } else {
+                frames.last_mut()?.2 += 1;
+            }
+        }
+
+        return Some(frames);
+    }
+}
+
+fn find_advanced_item(group: &LRGroup, item: &LRItem) -> Option<usize> {
+    return group.item_list.iter().position(|group_item| {
+        group_item.pattern_id == item.pattern_id
+            && group_item.current_position == item.current_position + 1
+    });
+}
+
+/// Search the shortest symbols from any start group to the group.
+fn get_shortest_prefix(
+    lr_group_map: &HashMap<usize, LRGroup>,
+    start_groups: usize,
+    group_number: usize,
+) -> Vec<BNFSymbol> {
+    let mut parent_map = HashMap::<usize, Option<(usize, BNFSymbol)>>::new();
+    let mut queue = VecDeque::<usize>::new();
+
+    for start_group_number in 0..start_groups {
+        parent_map.insert(start_group_number, None);
+        queue.push_back(start_group_number);
+    }
+
+    while let Some(number) = queue.pop_front() {
+        if number == group_number {
+            break;
+        }
+
+        let mut transitions = lr_group_map[&number]
+            .next_group_number_map
+            .iter()
+            .collect::<Vec<_>>();
+        transitions.sort_by_key(|(_, next_number)| **next_number);
+
+        for (symbol, next_number) in transitions {
+            if parent_map.contains_key(next_number) {
+                continue;
+            }
+            parent_map.insert(*next_number, Some((number, symbol.clone())));
+            queue.push_back(*next_number);
+        }
+    }
+
+    let mut prefix = Vec::<BNFSymbol>::new();
+    let mut number = group_number;
+    while let Some(Some((parent, symbol))) = parent_map.get(&number) {
+        prefix.push(symbol.clone());
+        number = *parent;
+    }
+    prefix.reverse();
+
+    return prefix;
+}
+
+/// Show the sentential form of a derivation, e.g. 'expr "+" expr • "+" expr'.
+fn display_example(frames: &Vec<DerivationFrame>, lookahead: Option<&BNFSymbol>) -> String {
+    let mut before = Vec::<BNFSymbol>::new();
+    let mut after = Vec::<BNFSymbol>::new();
+
+    for (index, (_, pattern, position)) in frames.iter().enumerate() {
+        before.extend(pattern[..*position].iter().cloned());
+
+        // the symbol at the position is expanded by the next frame
+        let rest_start = if index + 1 == frames.len() {
+            *position
+        } else {
+            *position + 1
+        };
+        let mut rest = pattern[rest_start.min(pattern.len())..].to_vec();
+        rest.extend(after);
+        after = rest;
+    }
+
+    let mut example = display_symbols(&before);
+    example += if before.is_empty() { "•" } else { " •" };
+
+    match lookahead {
+        Some(symbol) if after.is_empty() => {
+            example += format!(" {}", display_symbol(symbol)).as_str();
+        }
+        _ => {
+            if !after.is_empty() {
+                example += format!(" {}", display_symbols(&after)).as_str();
+            }
+        }
+    }
+
+    return example;
+}
+
+fn display_item(root_name: &str, pattern: &Vec<BNFSymbol>, position: usize) -> String {
+    let mut symbols = Vec::<String>::new();
+    for (index, symbol) in pattern.iter().enumerate() {
+        if index == position {
+            symbols.push("•".to_string());
+        }
+        symbols.push(display_symbol(symbol));
+    }
+    if position == pattern.len() {
+        symbols.push("•".to_string());
+    }
+
+    return format!(
+        "{} ::= {}",
+        display_symbol(&BNFSymbol::NonTerminalSymbolName(root_name.to_string())),
+        symbols.join(" ")
+    );
+}
+
+fn display_symbols(symbols: &Vec<BNFSymbol>) -> String {
+    return symbols
+        .iter()
+        .map(display_symbol)
+        .collect::<Vec<_>>()
+        .join(" ");
+}
+
+fn display_symbol(symbol: &BNFSymbol) -> String {
+    return match symbol {
+        BNFSymbol::NonTerminalSymbolName(name) => {
+            // unnamed patterns made by '( )', '{ }' and '[ ]'
+            if name.starts_with(' ') {
+                format!("<{}>", name.trim_start())
+            } else {
+                name.clone()
+            }
+        }
+        BNFSymbol::TerminalSymbolString(string) => format!("{:?}", string),
+        BNFSymbol::TerminalSymbolRegex(regex) => format!("r\"{}\"", regex),
+        BNFSymbol::TerminalSymbolFunction(function) => format!("fn ({})", function),
+        BNFSymbol::TerminalSymbolLayout(name) => name.clone(),
+        BNFSymbol::TerminalSymbolError => ERROR_SYMBOL_NAME.to_string(),
+        BNFSymbol::Null => "Null".to_string(),
+        BNFSymbol::EOF => "EOF".to_string(),
+    };
+}
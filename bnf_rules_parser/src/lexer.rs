@@ -1,58 +1,226 @@
+use crate::line_index::LineIndex;
 use regex::Regex;
-use std::cmp::min;
-use std::collections::HashMap;
-use std::rc::Rc;
+use regex_automata::dfa::{dense, Automaton, StartKind};
+use regex_automata::{Anchored, Input, MatchKind};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Symbol id of tokens which are skipped, e.g. whitespaces and comments.
+pub const SKIP_SYMBOL_ID: u32 = u32::MAX;
+
+/// Symbol id of tokens which wrap unrecognized input, made by 'Lexer::scan_with_recovery'.
+pub const ERROR_SYMBOL_ID: u32 = u32::MAX - 1;
+
+/// Symbol id of the EOF token, which is always the first id of the parse table.
+pub const EOF_SYMBOL_ID: u32 = 0;
+
+/// Whitespaces skipped by default, unless '#[skip_default = false]' is specified.
+pub const DEFAULT_SKIP_REGEX: &str = r"[ 　\t\r\n]+";
 
 pub struct Lexer {
-    terminal_symbols: Vec<Rc<TerminalSymbol>>,
-    eof_symbol: Rc<TerminalSymbol>,
+    /// The first mode is used at the start of the source.
+    modes: Vec<ModeSymbols>,
+    eof_symbol: Arc<TerminalSymbol>,
+    error_symbol: Arc<TerminalSymbol>,
+    /// Specified by 'Lexer::with_layout'.
+    layout: Option<LayoutSymbols>,
+}
+
+/// Synthetic terminal symbols made from line breaks and indentation.
+struct LayoutSymbols {
+    indent: Arc<TerminalSymbol>,
+    dedent: Arc<TerminalSymbol>,
+    newline: Arc<TerminalSymbol>,
+}
+
+/// Terminal symbols of a lexer mode.
+struct ModeSymbols {
+    terminal_symbols: Vec<Arc<TerminalSymbol>>,
+    /// None if the DFA could not be built, then all symbols are tried one by one.
+    scanner: Option<DfaScanner>,
+    /// Indexes of 'Tokenizer::Functional' symbols, which are not in the DFA.
+    functional_symbol_indexes: Vec<usize>,
+}
+
+/// Terminal symbols which are tried while the mode is on the top of the mode stack.
+pub struct LexerMode {
+    pub terminal_symbols: Vec<TerminalSymbol>,
+    pub skip_symbols: Vec<TerminalSymbol>,
+}
+
+impl LexerMode {
+    pub fn new(terminal_symbols: Vec<TerminalSymbol>, skip_symbols: Vec<TerminalSymbol>) -> Self {
+        return Self {
+            terminal_symbols,
+            skip_symbols,
+        };
+    }
 }
 
 impl Lexer {
-    pub fn new(mut terminal_symbols: Vec<TerminalSymbol>) -> Self {
-        terminal_symbols.push(TerminalSymbol::new_from_regex(r"[ 　/t]+", u32::MAX));
+    pub fn new(terminal_symbols: Vec<TerminalSymbol>) -> Self {
+        let default_skip_symbol =
+            TerminalSymbol::new_from_regex(DEFAULT_SKIP_REGEX, SKIP_SYMBOL_ID);
+        return Self::new_with_skip_symbols(terminal_symbols, vec![default_skip_symbol]);
+    }
 
-        let mut symbols = Vec::new();
-        for symbol in terminal_symbols {
-            symbols.push(Rc::new(symbol));
+    /// The longest match wins, and if some symbols match the same length,
+    /// the earlier one in 'terminal_symbols' wins.<br>
+    /// Tokens matched by 'skip_symbols' are ignored.<br>
+    /// If a skip symbol and a terminal symbol match the same length, the terminal symbol wins.
+    pub fn new_with_skip_symbols(
+        terminal_symbols: Vec<TerminalSymbol>,
+        skip_symbols: Vec<TerminalSymbol>,
+    ) -> Self {
+        return Self::new_with_modes(vec![LexerMode::new(terminal_symbols, skip_symbols)]);
+    }
+
+    /// The lexer keeps a stack of modes, which starts with the first mode.<br>
+    /// Only symbols of the mode on the top are tried, see 'TerminalSymbol::with_push'.
+    pub fn new_with_modes(modes: Vec<LexerMode>) -> Self {
+        let mut mode_symbols = Vec::new();
+
+        for mode in modes {
+            let mut terminal_symbols = mode.terminal_symbols;
+            for mut skip_symbol in mode.skip_symbols {
+                skip_symbol.symbol_id = SKIP_SYMBOL_ID;
+                terminal_symbols.push(skip_symbol);
+            }
+
+            let mut symbols = Vec::new();
+            for symbol in terminal_symbols {
+                symbols.push(Arc::new(symbol));
+            }
+
+            let functional_symbol_indexes = symbols
+                .iter()
+                .enumerate()
+                .filter(|(_, symbol)| matches!(symbol.tokenizer, Tokenizer::Functional(_)))
+                .map(|(index, _)| index)
+                .collect();
+
+            mode_symbols.push(ModeSymbols {
+                scanner: DfaScanner::new(&symbols),
+                terminal_symbols: symbols,
+                functional_symbol_indexes,
+            });
         }
 
         return Self {
-            terminal_symbols: symbols,
-            eof_symbol: Rc::new(TerminalSymbol::new_from_string("EOF", 0)),
+            modes: mode_symbols,
+            eof_symbol: Arc::new(TerminalSymbol::new_from_string("EOF", 0)),
+            error_symbol: Arc::new(TerminalSymbol::new_from_string("ERROR", ERROR_SYMBOL_ID)),
+            layout: None,
         };
     }
 
+    /// Make NEWLINE tokens at line breaks between tokens,
+    /// and INDENT or DEDENT tokens when the indentation of the next line changes.<br>
+    /// They are made only while no lexer mode is pushed.
+    /// Returns 'UnexpectedCharacter' if a dedent does not match any outer indentation,
+    /// or if tabs and spaces are mixed inconsistently, see 'LayoutState'.
+    pub fn with_layout(mut self, indent_id: u32, dedent_id: u32, newline_id: u32) -> Self {
+        self.layout = Some(LayoutSymbols {
+            indent: Arc::new(TerminalSymbol::new_from_string("INDENT", indent_id)),
+            dedent: Arc::new(TerminalSymbol::new_from_string("DEDENT", dedent_id)),
+            newline: Arc::new(TerminalSymbol::new_from_string("NEWLINE", newline_id)),
+        });
+        return self;
+    }
+
+    /// Try all symbols one by one instead of the DFA, which is only useful for comparison.
+    pub fn without_dfa(mut self) -> Self {
+        for mode in self.modes.iter_mut() {
+            mode.scanner = None;
+        }
+        return self;
+    }
+
     pub fn scan<'input>(
         &self,
         source: &'input str,
     ) -> Result<Vec<Token<'input>>, UnexpectedCharacter> {
+        let stop = |mut unexpected: UnexpectedCharacter| {
+            LineIndex::new(source).set_line_and_column(&mut unexpected.position);
+            return Err(unexpected);
+        };
+        return self.scan_tokens(source, &stop).map(|(tokens, _)| tokens);
+    }
+
+    /// Scans all tokens without stopping at unrecognized input.<br>
+    /// Each run of unrecognized input is wrapped in a token of 'ERROR_SYMBOL_ID',
+    /// and returned with an 'UnexpectedCharacter' for it.
+    pub fn scan_with_recovery<'input>(
+        &self,
+        source: &'input str,
+    ) -> (Vec<Token<'input>>, Vec<UnexpectedCharacter>) {
+        return match self.scan_tokens::<Infallible>(source, &|_| Ok(())) {
+            Ok(result) => result,
+            Err(never) => match never {},
+        };
+    }
+
+    /// Scans all tokens, 'stop' decides whether scanning stops with an error at unrecognized input.
+    /// Otherwise the input is wrapped in an error token, and returned with the tokens.
+    fn scan_tokens<'input, E>(
+        &self,
+        source: &'input str,
+        stop: &dyn Fn(UnexpectedCharacter) -> Result<(), E>,
+    ) -> Result<(Vec<Token<'input>>, Vec<UnexpectedCharacter>), E> {
         let source_length = source.len();
 
         if source_length == 0 {
             let position = TokenPosition::new(0, 0, 1, 1);
-            return Ok(vec![Token::new_eof(position, self.eof_symbol.clone())]);
+            return Ok((
+                vec![Token::new_eof(position, self.eof_symbol.clone())],
+                Vec::new(),
+            ));
         }
 
+        let mut errors = Vec::<UnexpectedCharacter>::new();
         let mut tokens = Vec::<Token>::new();
         let mut current_byte_position = 0;
+        let mut mode_stack = vec![0];
+        let mut layout = LayoutState::new();
 
         loop {
+            let mode = *mode_stack.last().unwrap();
             let token = match self.read_until_token_found(
+                mode,
                 &source[current_byte_position..],
                 &mut current_byte_position,
+                &|_| true,
             ) {
                 Ok(token) => token,
-                Err(mut unexpected) => {
-                    let mut position_map = HashMap::new();
-                    position_map
-                        .insert(unexpected.position.start_position, &mut unexpected.position);
-                    Self::set_line_and_column_info(source, &mut position_map);
-
-                    return Err(unexpected);
+                Err(unexpected) => {
+                    let character = unexpected.character;
+                    stop(unexpected)?;
+
+                    let token = self.read_error_token(mode, source, &mut current_byte_position);
+                    errors.push(UnexpectedCharacter {
+                        position: token.position.clone(),
+                        character,
+                    });
+                    token
                 }
             };
-            if token.symbol_id != u32::MAX {
+            let is_layout_mode = mode_stack.len() == 1;
+            token.terminal_symbol.change_mode(&mut mode_stack);
+
+            if token.symbol_id != SKIP_SYMBOL_ID {
+                if let (Some(symbols), true) = (&self.layout, is_layout_mode) {
+                    let start_position = token.position.start_position;
+                    let (layout_tokens, error) =
+                        layout.tokens_before(symbols, source, start_position);
+                    tokens.extend(layout_tokens);
+                    if let Some(unexpected) = error {
+                        stop(unexpected.clone())?;
+                        errors.push(unexpected);
+                    }
+                }
+                layout.previous_end = Some(current_byte_position);
                 tokens.push(token);
             }
 
@@ -61,32 +229,134 @@ impl Lexer {
             }
         }
 
-        Self::set_line_and_column_info_for_tokens(&source, &mut tokens);
+        if let Some(symbols) = &self.layout {
+            tokens.extend(layout.tokens_at_end(symbols, source));
+        }
 
         let eof_position = TokenPosition::new(source_length - 1, 0, 0, 0);
-        let mut eof_token = Token::new_eof(eof_position, self.eof_symbol.clone());
-        Self::set_line_and_column_info_for_eof_token(&source, &mut eof_token);
-        tokens.push(eof_token);
+        tokens.push(Token::new_eof(eof_position, self.eof_symbol.clone()));
 
-        return Ok(tokens);
+        let line_index = LineIndex::new(source);
+        for token in tokens.iter_mut() {
+            line_index.set_line_and_column(&mut token.position);
+        }
+        for error in errors.iter_mut() {
+            line_index.set_line_and_column(&mut error.position);
+        }
+
+        return Ok((tokens, errors));
+    }
+
+    /// Wraps unrecognized input until some symbol of the mode matches.
+    fn read_error_token<'input>(
+        &self,
+        mode: usize,
+        source: &'input str,
+        current_byte_position: &mut usize,
+    ) -> Token<'input> {
+        let start_position = *current_byte_position;
+        let mut end_position = start_position;
+
+        for char in source[start_position..].chars() {
+            end_position += char.len_utf8();
+
+            let mut byte_position = end_position;
+            if end_position == source.len()
+                || self
+                    .read_until_token_found(
+                        mode,
+                        &source[end_position..],
+                        &mut byte_position,
+                        &|_| true,
+                    )
+                    .is_ok()
+            {
+                break;
+            }
+        }
+
+        *current_byte_position = end_position;
+        return Token::new(
+            TokenPosition::new(start_position, end_position - start_position, 0, 0),
+            &source[start_position..end_position],
+            self.error_symbol.clone(),
+            ERROR_SYMBOL_ID,
+        );
+    }
+
+    /// Scans tokens one by one, see 'OnDemandScanner'.
+    pub fn scan_on_demand<'input>(&self, source: &'input str) -> OnDemandScanner<'_, 'input> {
+        return OnDemandScanner {
+            lexer: self,
+            source,
+            byte_position: 0,
+            line_index: LineIndex::new(source),
+            mode_stack: vec![0],
+            layout: LayoutState::new(),
+            layout_tokens: VecDeque::new(),
+            recover: false,
+            errors: Vec::new(),
+        };
     }
 
     fn read_until_token_found<'input>(
         &self,
+        mode: usize,
         current_input: &'input str,
         current_byte_position: &mut usize,
+        is_acceptable: &dyn Fn(u32) -> bool,
     ) -> Result<Token<'input>, UnexpectedCharacter> {
         let mut terminal_symbol = self.eof_symbol.clone();
         let mut text_length = 0;
+        let mut symbol_index = usize::MAX;
 
         let start_position = *current_byte_position;
+        let mode = &self.modes[mode];
 
-        for symbol in self.terminal_symbols.iter() {
-            let length = symbol.tokenize(current_input);
-            if length > text_length {
-                terminal_symbol = symbol.clone();
+        // skip symbols are always acceptable
+        let accepts = |index: usize| {
+            let symbol_id = mode.terminal_symbols[index].symbol_id;
+            symbol_id == SKIP_SYMBOL_ID || is_acceptable(symbol_id)
+        };
+        let mut select = |index: usize, length: usize| {
+            if length > text_length || (length == text_length && index < symbol_index) {
+                symbol_index = index;
                 text_length = length;
             }
+        };
+
+        match mode
+            .scanner
+            .as_ref()
+            .and_then(|scanner| scanner.find_candidates(current_input))
+        {
+            Some(candidates) => {
+                // the length is decided by each symbol, e.g. 'r"a|ab"' matches only "a"
+                for index in candidates {
+                    if accepts(index) {
+                        select(index, mode.terminal_symbols[index].tokenize(current_input));
+                    }
+                }
+                for index in mode.functional_symbol_indexes.iter() {
+                    if accepts(*index) {
+                        select(
+                            *index,
+                            mode.terminal_symbols[*index].tokenize(current_input),
+                        );
+                    }
+                }
+            }
+            _ => {
+                for (index, symbol) in mode.terminal_symbols.iter().enumerate() {
+                    if accepts(index) {
+                        select(index, symbol.tokenize(current_input));
+                    }
+                }
+            }
+        }
+
+        if text_length != 0 {
+            terminal_symbol = mode.terminal_symbols[symbol_index].clone();
         }
 
         let token_text = &current_input[..text_length];
@@ -95,23 +365,13 @@ impl Lexer {
 
         if text_length == 0 {
             Err(UnexpectedCharacter {
-                position: TokenPosition {
-                    start_position,
-                    text_length: 1,
-                    line: 0,
-                    column: 0,
-                },
+                position: TokenPosition::new(start_position, 1, 0, 0),
                 character: current_input.chars().next().unwrap(),
             })
         } else {
             let symbol_id = terminal_symbol.symbol_id;
             Ok(Token {
-                position: TokenPosition {
-                    start_position,
-                    text_length,
-                    line: 0,
-                    column: 0,
-                },
+                position: TokenPosition::new(start_position, text_length, 0, 0),
                 text: token_text,
                 terminal_symbol,
                 is_eof: false,
@@ -119,88 +379,390 @@ impl Lexer {
             })
         }
     }
+}
 
-    fn set_line_and_column_info_for_tokens(input: &str, tokens: &mut Vec<Token>) {
-        let mut position_map = HashMap::<usize, &mut TokenPosition>::new();
-        for token in tokens.iter_mut() {
-            position_map.insert(token.position.start_position, &mut token.position);
+/// Indentation levels for 'Lexer::with_layout'.<br>
+/// Indentation is compared as text rather than widths, so a tab is never equal to some spaces.
+/// A deeper indentation must start with the outer one, otherwise it is an error.
+#[derive(Clone)]
+struct LayoutState {
+    /// Indentation of each level, the first is always empty.
+    indent_stack: Vec<String>,
+    /// The end of the last token, None before the first token.
+    previous_end: Option<usize>,
+    is_finished: bool,
+}
+
+impl LayoutState {
+    fn new() -> Self {
+        return Self {
+            indent_stack: vec![String::new()],
+            previous_end: None,
+            is_finished: false,
+        };
+    }
+
+    /// NEWLINE, INDENT and DEDENT tokens before the token which starts at the position.<br>
+    /// The indentation is only measured if the token is the first on its line,
+    /// e.g. not after the end of a multi-line comment.<br>
+    /// If a dedent does not match any outer indentation, or tabs and spaces are mixed inconsistently,
+    /// it is returned as an error and the indentation becomes a new level.
+    fn tokens_before<'input>(
+        &mut self,
+        symbols: &LayoutSymbols,
+        source: &'input str,
+        start_position: usize,
+    ) -> (Vec<Token<'input>>, Option<UnexpectedCharacter>) {
+        let mut tokens = Vec::new();
+
+        if let Some(previous_end) = self.previous_end.replace(start_position) {
+            match Self::newline_token(symbols, source, previous_end, start_position) {
+                Some(token) => tokens.push(token),
+                _ => return (tokens, None),
+            }
+        }
+
+        let line_start = source[..start_position]
+            .rfind(['\n', '\r'])
+            .map_or(0, |index| index + 1);
+        let indent = &source[line_start..start_position];
+        if indent.contains(|c| c != ' ' && c != '\t') {
+            return (tokens, None);
+        }
+        let position = TokenPosition::new(start_position, 0, 0, 0);
+
+        let current_indent = self.indent_stack.last().unwrap();
+        if indent.len() > current_indent.len() && indent.starts_with(current_indent.as_str()) {
+            self.indent_stack.push(indent.to_string());
+            tokens.push(Token::new(
+                position,
+                "",
+                symbols.indent.clone(),
+                symbols.indent.symbol_id,
+            ));
+            return (tokens, None);
+        }
+
+        while !indent.starts_with(self.indent_stack.last().unwrap().as_str()) {
+            self.indent_stack.pop();
+            tokens.push(Token::new(
+                position.clone(),
+                "",
+                symbols.dedent.clone(),
+                symbols.dedent.symbol_id,
+            ));
         }
-        Self::set_line_and_column_info(input, &mut position_map);
+        if indent != self.indent_stack.last().unwrap() {
+            self.indent_stack.push(indent.to_string());
+            let error = UnexpectedCharacter {
+                position: TokenPosition::new(start_position, 1, 0, 0),
+                character: source[start_position..].chars().next().unwrap(),
+            };
+            return (tokens, Some(error));
+        }
+
+        return (tokens, None);
     }
 
-    fn set_line_and_column_info_for_eof_token(input: &str, token: &mut Token) {
-        let mut position_map = HashMap::<usize, &mut TokenPosition>::new();
-        position_map.insert(token.position.start_position, &mut token.position);
-        Self::set_line_and_column_info(input, &mut position_map);
+    /// NEWLINE after the last token and DEDENT for each indentation.
+    fn tokens_at_end<'input>(
+        &mut self,
+        symbols: &LayoutSymbols,
+        source: &'input str,
+    ) -> Vec<Token<'input>> {
+        let previous_end = match self.previous_end {
+            Some(previous_end) if !self.is_finished => previous_end,
+            _ => return Vec::new(),
+        };
+        self.is_finished = true;
+
+        let end_position = TokenPosition::new(source.len(), 0, 0, 0);
+        let newline_token = Self::newline_token(symbols, source, previous_end, source.len())
+            .unwrap_or_else(|| {
+                Token::new(
+                    end_position.clone(),
+                    "",
+                    symbols.newline.clone(),
+                    symbols.newline.symbol_id,
+                )
+            });
+
+        let mut tokens = vec![newline_token];
+        while self.indent_stack.len() > 1 {
+            self.indent_stack.pop();
+            tokens.push(Token::new(
+                end_position.clone(),
+                "",
+                symbols.dedent.clone(),
+                symbols.dedent.symbol_id,
+            ));
+        }
+        return tokens;
     }
 
-    fn set_line_and_column_info(
-        input: &str,
-        position_map: &mut HashMap<usize, &mut TokenPosition>,
-    ) {
-        let mut line = 1;
-        let mut column = 1;
-        let mut byte_position = 0;
+    /// NEWLINE token of the first line break in the range.
+    fn newline_token<'input>(
+        symbols: &LayoutSymbols,
+        source: &'input str,
+        start_position: usize,
+        end_position: usize,
+    ) -> Option<Token<'input>> {
+        let break_position =
+            start_position + source[start_position..end_position].find(['\n', '\r'])?;
+        let length = if source[break_position..].starts_with("\r\n") {
+            2
+        } else {
+            1
+        };
 
-        let mut previous_column = column;
-        let mut previous_char: Option<char> = None;
+        return Some(Token::new(
+            TokenPosition::new(break_position, length, 0, 0),
+            &source[break_position..break_position + length],
+            symbols.newline.clone(),
+            symbols.newline.symbol_id,
+        ));
+    }
+}
 
-        let mut input_chars = input.chars();
+/// Reads tokens one by one, only with terminal symbols which the parser can accept.<br>
+/// Created by 'Lexer::scan_on_demand'.
+#[derive(Clone)]
+pub struct OnDemandScanner<'lexer, 'input> {
+    lexer: &'lexer Lexer,
+    source: &'input str,
+    byte_position: usize,
+    line_index: LineIndex<'input>,
+    mode_stack: Vec<usize>,
+    layout: LayoutState,
+    /// Layout tokens which are made but not returned yet.
+    layout_tokens: VecDeque<Token<'input>>,
+    recover: bool,
+    errors: Vec<UnexpectedCharacter>,
+}
 
+impl<'lexer, 'input> OnDemandScanner<'lexer, 'input> {
+    /// Returns the next token which is not skipped, or the EOF token at the end of the source.<br>
+    /// Terminal symbols whose ids are rejected by 'is_acceptable' are not tried,
+    /// unless no acceptable symbol matches.
+    pub fn next_token(
+        &mut self,
+        is_acceptable: &dyn Fn(u32) -> bool,
+    ) -> Result<Token<'input>, UnexpectedCharacter> {
         loop {
-            let char = match input_chars.next() {
-                Some(char) => char,
-                None => break,
-            };
-
-            match position_map.get_mut(&byte_position) {
-                Some(position) => {
-                    position.line = line;
-                    position.column = column;
-                }
-                _ => {}
+            if let Some(mut token) = self.layout_tokens.pop_front() {
+                self.line_index.set_line_and_column(&mut token.position);
+                return Ok(token);
             }
 
-            let line_feed = if char == '\n' {
-                match previous_char {
-                    Some(previous_char) => {
-                        if previous_char == '\r' {
-                            //for CRLF
-                            column = previous_column;
-                            false
-                        } else {
-                            //for LF
-                            true
+            if let (Some(symbols), 1) = (&self.lexer.layout, self.mode_stack.len()) {
+                // layout tokens are decided by the position of the next token
+                self.skip();
+                let layout_tokens = if self.byte_position == self.source.len() {
+                    self.layout.tokens_at_end(symbols, self.source)
+                } else {
+                    let (layout_tokens, error) =
+                        self.layout
+                            .tokens_before(symbols, self.source, self.byte_position);
+                    if let Some(mut unexpected) = error {
+                        self.line_index
+                            .set_line_and_column(&mut unexpected.position);
+                        if !self.recover {
+                            return Err(unexpected);
                         }
+                        self.errors.push(unexpected);
                     }
-                    _ => {
-                        //for LF
-                        true
-                    }
+                    layout_tokens
+                };
+                if !layout_tokens.is_empty() {
+                    self.layout_tokens.extend(layout_tokens);
+                    continue;
                 }
-            } else if char == '\r' {
-                //for CR
-                true
-            } else {
-                false
+            }
+
+            if self.byte_position == self.source.len() {
+                return Ok(self.eof_token());
+            }
+
+            let start_position = self.byte_position;
+            let mode = *self.mode_stack.last().unwrap();
+            let mut result = self.lexer.read_until_token_found(
+                mode,
+                &self.source[start_position..],
+                &mut self.byte_position,
+                is_acceptable,
+            );
+            if result.is_err() {
+                // an unacceptable token is a syntax error rather than an error of the lexer
+                result = self.lexer.read_until_token_found(
+                    mode,
+                    &self.source[start_position..],
+                    &mut self.byte_position,
+                    &|_| true,
+                );
+            }
+
+            let result = match result {
+                Err(unexpected) if self.recover => {
+                    let token =
+                        self.lexer
+                            .read_error_token(mode, self.source, &mut self.byte_position);
+                    let mut position = token.position.clone();
+                    self.line_index.set_line_and_column(&mut position);
+                    self.errors.push(UnexpectedCharacter {
+                        position,
+                        character: unexpected.character,
+                    });
+                    Ok(token)
+                }
+                result => result,
             };
 
-            if line_feed {
-                previous_column = column;
-                line += 1;
-                column = 0;
+            match result {
+                Ok(mut token) => {
+                    token.terminal_symbol.change_mode(&mut self.mode_stack);
+                    if token.symbol_id == SKIP_SYMBOL_ID {
+                        continue;
+                    }
+                    self.layout.previous_end = Some(self.byte_position);
+                    self.line_index.set_line_and_column(&mut token.position);
+                    return Ok(token);
+                }
+                Err(mut unexpected) => {
+                    self.line_index
+                        .set_line_and_column(&mut unexpected.position);
+                    return Err(unexpected);
+                }
             }
+        }
+    }
+
+    /// Returns tokens of 'ERROR_SYMBOL_ID' instead of errors, see 'Lexer::scan_with_recovery'.
+    pub fn with_recovery(mut self) -> Self {
+        self.recover = true;
+        return self;
+    }
 
-            previous_char = Some(char);
+    /// Errors which are recovered so far.
+    pub fn take_errors(&mut self) -> Vec<UnexpectedCharacter> {
+        return std::mem::take(&mut self.errors);
+    }
+
+    /// Reads skipped tokens as long as they match.
+    fn skip(&mut self) {
+        while self.byte_position < self.source.len() {
+            let result = self.lexer.read_until_token_found(
+                *self.mode_stack.last().unwrap(),
+                &self.source[self.byte_position..],
+                &mut self.byte_position,
+                &|_| false,
+            );
+            match result {
+                Ok(token) => token.terminal_symbol.change_mode(&mut self.mode_stack),
+                _ => break,
+            }
+        }
+    }
 
-            byte_position += char.len_utf8();
+    /// Zero length position at the end of the last token.
+    pub fn current_position(&mut self) -> TokenPosition {
+        let mut position = TokenPosition::new(self.byte_position, 0, 0, 0);
+        self.line_index.set_line_and_column(&mut position);
+        return position;
+    }
 
-            column += 1;
+    fn eof_token(&self) -> Token<'input> {
+        let eof_symbol = self.lexer.eof_symbol.clone();
+        if self.source.is_empty() {
+            return Token::new_eof(TokenPosition::new(0, 0, 1, 1), eof_symbol);
         }
+
+        // same as 'Lexer::scan'
+        let start_position = self.source.len() - 1;
+        let mut position = TokenPosition::new(start_position, 0, 0, 0);
+        self.line_index.set_line_and_column(&mut position);
+        return Token::new_eof(position, eof_symbol);
     }
 }
 
-#[derive(Debug)]
+/// All string and regex symbols of a lexer compiled into one DFA,
+/// which finds symbols matching at the head of the input in a single pass.
+struct DfaScanner {
+    dfa: dense::DFA<Vec<u32>>,
+    /// Index in 'Lexer::terminal_symbols' for each pattern of the DFA.
+    symbol_indexes: Vec<usize>,
+}
+
+impl DfaScanner {
+    fn new(symbols: &Vec<Arc<TerminalSymbol>>) -> Option<Self> {
+        let mut patterns = Vec::new();
+        let mut symbol_indexes = Vec::new();
+        for (index, symbol) in symbols.iter().enumerate() {
+            let pattern = match &symbol.tokenizer {
+                Tokenizer::Keyword(keyword) => regex::escape(keyword),
+                Tokenizer::Regex(regex) => regex.as_str().to_string(),
+                Tokenizer::Functional(_) => continue,
+            };
+            patterns.push(pattern);
+            symbol_indexes.push(index);
+        }
+
+        let config = dense::Config::new()
+            .match_kind(MatchKind::All)
+            .start_kind(StartKind::Anchored)
+            .unicode_word_boundary(true)
+            .determinize_size_limit(Some(64 * 1024 * 1024));
+        let dfa = dense::Builder::new()
+            .configure(config)
+            .build_many(&patterns)
+            .ok()?;
+
+        return Some(Self {
+            dfa,
+            symbol_indexes,
+        });
+    }
+
+    /// Symbol indexes which have a non-empty match at the head of the input.<br>
+    /// Lengths are not decided here, because the DFA finds all matches
+    /// while a regex takes the leftmost-first one, e.g. '/\*.*?\*/'.<br>
+    /// Returns None if the DFA gives up, e.g. for '\b' before a non-ASCII character.
+    fn find_candidates(&self, input: &str) -> Option<Vec<usize>> {
+        let mut candidates = Vec::<usize>::new();
+
+        let start = Input::new(input).anchored(Anchored::Yes);
+        let mut state = self.dfa.start_state_forward(&start).ok()?;
+
+        // matches are delayed by one byte in the DFA
+        for length in 0..=input.len() {
+            state = match input.as_bytes().get(length) {
+                Some(byte) => self.dfa.next_state(state, *byte),
+                _ => self.dfa.next_eoi_state(state),
+            };
+
+            if !self.dfa.is_special_state(state) {
+                continue;
+            }
+            if self.dfa.is_dead_state(state) {
+                break;
+            }
+            if self.dfa.is_quit_state(state) {
+                return None;
+            }
+            if self.dfa.is_match_state(state) && length > 0 {
+                for i in 0..self.dfa.match_len(state) {
+                    let index = self.symbol_indexes[self.dfa.match_pattern(state, i).as_usize()];
+                    if !candidates.contains(&index) {
+                        candidates.push(index);
+                    }
+                }
+            }
+        }
+
+        return Some(candidates);
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct UnexpectedCharacter {
     pub position: TokenPosition,
     pub character: char,
@@ -212,6 +774,18 @@ type TokenizerFn = fn(input: &str) -> usize;
 pub struct TerminalSymbol {
     tokenizer: Tokenizer,
     symbol_id: u32,
+    /// Keywords which are never matched by this symbol.
+    reserved: &'static [&'static str],
+    mode_action: Option<ModeAction>,
+}
+
+/// Change of the lexer mode stack when a terminal symbol is matched.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ModeAction {
+    /// Push the index of the mode.
+    Push(usize),
+    /// Pop the mode, the first mode is never popped.
+    Pop,
 }
 
 #[derive(Debug)]
@@ -226,6 +800,8 @@ impl TerminalSymbol {
         Self {
             tokenizer: Tokenizer::Functional(tokenizer),
             symbol_id,
+            reserved: &[],
+            mode_action: None,
         }
     }
 
@@ -233,6 +809,8 @@ impl TerminalSymbol {
         Self {
             tokenizer: Tokenizer::Keyword(keyword),
             symbol_id,
+            reserved: &[],
+            mode_action: None,
         }
     }
 
@@ -240,10 +818,54 @@ impl TerminalSymbol {
         Self {
             tokenizer: Tokenizer::Regex(Regex::new(format!("^({})", regex).as_str()).unwrap()),
             symbol_id,
+            reserved: &[],
+            mode_action: None,
+        }
+    }
+
+    /// Reject matches which are exactly one of the keywords, e.g. an identifier 'if'.
+    pub fn with_reserved(mut self, reserved: &'static [&'static str]) -> Self {
+        self.reserved = reserved;
+        return self;
+    }
+
+    /// Push the mode of the index when this symbol is matched.
+    pub fn with_push(mut self, mode: usize) -> Self {
+        self.mode_action = Some(ModeAction::Push(mode));
+        return self;
+    }
+
+    /// Pop the current mode when this symbol is matched.
+    pub fn with_pop(mut self) -> Self {
+        self.mode_action = Some(ModeAction::Pop);
+        return self;
+    }
+
+    fn change_mode(&self, mode_stack: &mut Vec<usize>) {
+        match self.mode_action {
+            Some(ModeAction::Push(mode)) => mode_stack.push(mode),
+            Some(ModeAction::Pop) => {
+                if mode_stack.len() > 1 {
+                    mode_stack.pop();
+                }
+            }
+            _ => {}
         }
     }
 
     pub fn tokenize(&self, input: &str) -> usize {
+        let length = self.tokenize_without_reserved(input);
+        if self.is_reserved(&input[..length]) {
+            return 0; // reject
+        }
+        return length;
+    }
+
+    fn is_reserved(&self, text: &str) -> bool {
+        return self.reserved.contains(&text);
+    }
+
+    fn tokenize_without_reserved(&self, input: &str) -> usize {
         return match &self.tokenizer {
             Tokenizer::Functional(tokenizer_fn) => tokenizer_fn(input),
             Tokenizer::Keyword(keyword) => {
@@ -281,7 +903,7 @@ impl TerminalSymbol {
 pub struct Token<'input> {
     pub position: TokenPosition,
     pub text: &'input str,
-    pub terminal_symbol: Rc<TerminalSymbol>,
+    pub terminal_symbol: Arc<TerminalSymbol>,
     pub is_eof: bool,
     pub symbol_id: u32,
 }
@@ -290,7 +912,7 @@ impl<'input> Token<'input> {
     pub fn new(
         position: TokenPosition,
         text: &'input str,
-        terminal_symbol: Rc<TerminalSymbol>,
+        terminal_symbol: Arc<TerminalSymbol>,
         symbol_id: u32,
     ) -> Self {
         return Self {
@@ -302,62 +924,83 @@ impl<'input> Token<'input> {
         };
     }
 
-    pub fn new_eof(position: TokenPosition, terminal_symbol: Rc<TerminalSymbol>) -> Self {
+    pub fn new_eof(position: TokenPosition, terminal_symbol: Arc<TerminalSymbol>) -> Self {
         return Self {
             position,
             text: "",
             terminal_symbol,
             is_eof: true,
-            symbol_id: 0,
+            symbol_id: EOF_SYMBOL_ID,
         };
     }
 }
 
+/// Span of a token or a node in the source.<br>
+/// Lines and columns start from 1, the end is the line and column just after the last char.
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct TokenPosition {
     pub start_position: usize,
     pub text_length: usize,
     pub line: usize,
     pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
 }
 
 impl TokenPosition {
+    /// The end line and column are the same as the start, until 'LineIndex::set_line_and_column'.
     pub fn new(start_position: usize, text_length: usize, line: usize, column: usize) -> Self {
         return Self {
             start_position,
             text_length,
             line,
             column,
+            end_line: line,
+            end_column: column,
         };
     }
 
+    /// Byte range in the source, e.g. '&source[position.range()]'.
+    pub fn range(&self) -> Range<usize> {
+        return self.start_position..self.start_position + self.text_length;
+    }
+
     pub fn marge_start_position() -> Self {
         return Self {
             start_position: usize::MAX,
             text_length: 0,
             line: usize::MAX,
             column: usize::MAX,
+            end_line: usize::MAX,
+            end_column: usize::MAX,
         };
     }
 
+    /// Extends the span from the first start to the last end.<br>
+    /// Empty positions are ignored unless all of them are empty.
     pub fn marge(&mut self, other_token_position: &TokenPosition) {
-        if other_token_position.text_length != 0 {
-            self.text_length = if self.start_position == usize::MAX {
-                other_token_position.text_length
-            } else if other_token_position.start_position == usize::MAX {
-                self.text_length
-            } else {
-                if self.start_position < other_token_position.start_position {
-                    (other_token_position.start_position - self.start_position)
-                        + other_token_position.text_length
-                } else {
-                    (self.start_position - other_token_position.start_position) + self.text_length
-                }
-            };
+        if self.start_position == usize::MAX
+            || (self.text_length == 0 && other_token_position.text_length != 0)
+        {
+            *self = other_token_position.clone();
+            return;
+        }
+        if other_token_position.start_position == usize::MAX
+            || other_token_position.text_length == 0
+        {
+            return;
         }
 
-        self.start_position = min(self.start_position, other_token_position.start_position);
-        self.line = min(self.line, other_token_position.line);
-        self.column = min(self.column, other_token_position.column);
+        let end_position = self.range().end.max(other_token_position.range().end);
+        if other_token_position.start_position < self.start_position {
+            self.start_position = other_token_position.start_position;
+            self.line = other_token_position.line;
+            self.column = other_token_position.column;
+        }
+        if other_token_position.range().end == end_position {
+            self.end_line = other_token_position.end_line;
+            self.end_column = other_token_position.end_column;
+        }
+        self.text_length = end_position - self.start_position;
     }
 }
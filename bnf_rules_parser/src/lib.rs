@@ -1,57 +1,96 @@
+use crate::conflict::Conflict;
+use crate::lexer::SKIP_SYMBOL_ID;
+use crate::lint::{check_rules, LintWarning};
+use crate::symbol_set::SymbolSet;
+use crate::table::{compressed_table_code, dense_table_code};
+use crate::typed_ast::{OPTION_PATH, RESULT_PATH, VEC_PATH};
 use litrs::StringLit;
-use proc_macro2::{Delimiter, TokenTree};
+use proc_macro2::{Delimiter, Group, Ident, Literal, Span, TokenStream, TokenTree};
+use regex::Regex;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::mem;
 use syn::parse::{Parse, ParseStream};
 use syn::Error;
 
+mod action;
+mod conflict;
 pub mod lexer;
+pub mod line_index;
+pub mod lint;
 pub mod parser;
+pub mod symbol_set;
+pub mod table;
+mod typed_ast;
+mod values;
 
-pub fn parse_rules(tokens: &Vec<TokenTree>) -> Result<(HashMap<String, BNFRule>, bool), Error> {
+pub fn parse_rules(
+    tokens: &Vec<TokenTree>,
+) -> Result<(HashMap<String, BNFRule>, GrammarSettings), Error> {
     let mut non_terminal_symbol_name = String::new();
+    let mut non_terminal_symbol_span = Span::call_site();
     let mut buffered_tokens = Vec::<TokenTree>::new();
-    let mut rule_map = HashMap::<String, BNFRule>::new();
 
     let mut i = 0;
-    let mut token = &tokens[0];
+    let mut token = match tokens.first() {
+        Some(token) => token,
+        _ => return Err(Error::new(Span::call_site(), "No rules are specified.")),
+    };
 
-    let mut non_duplicate_number = NonDuplicateNumber::new();
-    let mut unnamed_pattern_map = HashMap::new();
+    let mut context = RuleContext::new();
 
-    let mut generate_code = true;
-    if let TokenTree::Punct(punctuation) = tokens.first().unwrap() {
+    let mut rule_attributes = RuleAttributes::new();
+    let mut next_rule_attributes = RuleAttributes::new();
+    while let TokenTree::Punct(punctuation) = token {
         if punctuation.as_char() != '#' {
             return Err(Error::new(token.span(), "Invalid syntax."));
         }
 
-        if let TokenTree::Group(group) = next(tokens, &mut i)? {
-            if group.delimiter() != Delimiter::Bracket {
-                return Err(Error::new(token.span(), "Invalid syntax."));
-            }
-
-            let mut i = 0;
-            let tokens = group.stream().into_iter().collect::<Vec<_>>();
-
-            check_current_identifier(&tokens, &mut i, "generate_code")?;
-            check_next_punct(&tokens, &mut i, '=')?;
-
-            if let TokenTree::Ident(identifier) = next(&tokens, &mut i)? {
-                generate_code = match identifier.to_string().as_str() {
-                    "true" => true,
-                    "false" => false,
-                    _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+        match next(tokens, &mut i)? {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => {
+                if is_rule_attribute(group) {
+                    parse_rule_attribute(group, &mut rule_attributes)?;
+                } else {
+                    parse_attribute(group, &mut context.settings)?;
                 }
             }
+            token => return Err(Error::new(token.span(), "Invalid syntax.")),
         }
 
-        i += 1;
-        token = &tokens[i];
+        token = next(tokens, &mut i)?;
     }
 
     let mut is_first_rule_token = true;
 
     loop {
+        if let TokenTree::Punct(punct) = token {
+            if punct.as_char() == '#' && !is_first_rule_token {
+                // attributes of the next rule
+                match next(tokens, &mut i)? {
+                    TokenTree::Group(group) if group.delimiter() == Delimiter::Bracket => {
+                        parse_rule_attribute(group, &mut next_rule_attributes)?;
+                    }
+                    token => return Err(Error::new(token.span(), "Invalid syntax.")),
+                }
+                token = next(tokens, &mut i)?;
+
+                let is_rule_start = match (token, tokens.get(i + 1)) {
+                    (TokenTree::Ident(_), Some(TokenTree::Punct(punct))) => {
+                        punct.as_char() == ':' || punct.as_char() == '#'
+                    }
+                    (TokenTree::Punct(punct), _) => punct.as_char() == '#',
+                    _ => false,
+                };
+                if !is_rule_start {
+                    return Err(Error::new(
+                        punct.span(),
+                        "Attributes must be placed before a rule.",
+                    ));
+                }
+                continue;
+            }
+        }
+
         buffered_tokens.push(token.clone());
 
         if is_first_rule_token {
@@ -66,6 +105,7 @@ pub fn parse_rules(tokens: &Vec<TokenTree>) -> Result<(HashMap<String, BNFRule>,
                     ))
                 }
             };
+            non_terminal_symbol_span = token.span();
 
             check_next_punct(&tokens, &mut i, ':')?;
             check_next_punct(&tokens, &mut i, ':')?;
@@ -73,19 +113,22 @@ pub fn parse_rules(tokens: &Vec<TokenTree>) -> Result<(HashMap<String, BNFRule>,
 
             token = next(&tokens, &mut i)?;
         } else {
-            match check_next_punct(&tokens, &mut i, ':') {
-                Ok(_) => {
+            // 'rule ::=' starts the next rule, 'label:symbol' is in the pattern
+            match is_rule_definition(&tokens, i) {
+                true => {
+                    i += 1;
                     buffered_tokens.pop();
 
                     parse_rule(
-                        &mut rule_map,
+                        &mut context,
                         &mut non_terminal_symbol_name,
+                        non_terminal_symbol_span,
                         &buffered_tokens,
-                        &mut non_duplicate_number,
-                        &mut unnamed_pattern_map,
+                        &rule_attributes,
                     )?;
 
                     buffered_tokens.clear();
+                    rule_attributes = mem::take(&mut next_rule_attributes);
 
                     non_terminal_symbol_name = match token {
                         TokenTree::Ident(ident) => ident.to_string(),
@@ -96,14 +139,15 @@ pub fn parse_rules(tokens: &Vec<TokenTree>) -> Result<(HashMap<String, BNFRule>,
                             ))
                         }
                     };
+                    non_terminal_symbol_span = token.span();
 
                     check_next_punct(&tokens, &mut i, ':')?;
                     check_next_punct(&tokens, &mut i, '=')?;
 
                     token = next(&tokens, &mut i)?;
                 }
-                Err(_) => {
-                    token = &tokens[i];
+                false => {
+                    token = next(&tokens, &mut i)?;
                 }
             }
         }
@@ -113,17 +157,367 @@ pub fn parse_rules(tokens: &Vec<TokenTree>) -> Result<(HashMap<String, BNFRule>,
         if i + 1 == tokens.len() {
             buffered_tokens.push(token.clone());
             parse_rule(
-                &mut rule_map,
+                &mut context,
                 &mut non_terminal_symbol_name,
+                non_terminal_symbol_span,
                 &buffered_tokens,
-                &mut non_duplicate_number,
-                &mut unnamed_pattern_map,
+                &rule_attributes,
             )?;
             break;
         }
     }
 
-    return Ok((rule_map, generate_code));
+    context.settings.check_lexer_modes()?;
+
+    return Ok((context.rule_map, context.settings));
+}
+
+fn parse_attribute(group: &Group, settings: &mut GrammarSettings) -> Result<(), Error> {
+    let tokens = group.stream().into_iter().collect::<Vec<_>>();
+    let name = match tokens.first() {
+        Some(TokenTree::Ident(identifier)) => identifier,
+        _ => return Err(Error::new(group.span(), "Invalid attribute.")),
+    };
+    let mut i = 0;
+
+    match name.to_string().as_str() {
+        "generate_code" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.generate_code = match token.to_string().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+            };
+        }
+        "left" | "right" | "nonassoc" => {
+            let associativity = match name.to_string().as_str() {
+                "left" => Associativity::Left,
+                "right" => Associativity::Right,
+                _ => Associativity::NonAssoc,
+            };
+            // Later declarations bind tighter, as in yacc.
+            let level = settings.precedence_map.len() + 1;
+
+            for token in parse_attribute_arguments(&tokens, name)? {
+                let symbol = parse_precedence_symbol(&token)?;
+                if settings.precedence_map.contains_key(&symbol) {
+                    return Err(Error::new(
+                        token.span(),
+                        "Precedence is already declared for this symbol.",
+                    ));
+                }
+                settings.precedence_map.insert(
+                    symbol,
+                    Precedence {
+                        level,
+                        associativity: associativity.clone(),
+                    },
+                );
+            }
+        }
+        "table" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.table_mode = match token.to_string().as_str() {
+                "lr1" => TableMode::Canonical,
+                "pager" => TableMode::Pager,
+                "lalr" => TableMode::LALR,
+                _ => {
+                    return Err(Error::new(
+                        token.span(),
+                        "Invalid table mode. Expected 'lr1', 'pager' or 'lalr'.",
+                    ))
+                }
+            };
+        }
+        "scan" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.scan_mode = match token.to_string().as_str() {
+                "batch" => ScanMode::Batch,
+                "on_demand" => ScanMode::OnDemand,
+                _ => {
+                    return Err(Error::new(
+                        token.span(),
+                        "Invalid scan mode. Expected 'batch' or 'on_demand'.",
+                    ))
+                }
+            };
+        }
+        "layout" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.layout = match token.to_string().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+            };
+        }
+        "typed_ast" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.typed_ast = match token.to_string().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+            };
+        }
+        "compress_table" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.compress_table = match token.to_string().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+            };
+        }
+        "default_reductions" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.default_reductions = match token.to_string().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+            };
+        }
+        "skip_default" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            settings.skip_default = match token.to_string().as_str() {
+                "true" => true,
+                "false" => false,
+                _ => return Err(Error::new(token.span(), "Invalid syntax.")),
+            };
+        }
+        "skip" => {
+            for token in parse_attribute_arguments(&tokens, name)? {
+                let symbol = match &token {
+                    TokenTree::Literal(literal) => parse_terminal_literal(literal)?,
+                    _ => return Err(Error::new(token.span(), "Invalid skip symbol.")),
+                };
+                settings.skip_symbols.push(symbol);
+            }
+        }
+        "sync" => {
+            for token in parse_attribute_arguments(&tokens, name)? {
+                let symbol = match &token {
+                    TokenTree::Literal(literal) => parse_terminal_literal(literal)?,
+                    _ => return Err(Error::new(token.span(), "Invalid sync symbol.")),
+                };
+                settings.sync_symbols.push((symbol, token.span()));
+            }
+        }
+        "entry" => {
+            for token in parse_attribute_arguments(&tokens, name)? {
+                let entry = match &token {
+                    TokenTree::Ident(ident) => ident.to_string(),
+                    _ => return Err(Error::new(token.span(), "Invalid rule name.")),
+                };
+                if settings.entries.iter().any(|defined| defined.0 == entry) {
+                    return Err(Error::new(token.span(), "Duplicate entry."));
+                }
+                settings.entries.push((entry, token.span()));
+            }
+        }
+        _ => return Err(Error::new(name.span(), "Unknown attribute.")),
+    }
+
+    return Ok(());
+}
+
+/// Whether the attribute is placed before a rule, not the whole grammar.
+fn is_rule_attribute(group: &Group) -> bool {
+    return match group.stream().into_iter().next() {
+        Some(TokenTree::Ident(identifier)) => {
+            let name = identifier.to_string();
+            matches!(
+                name.as_str(),
+                "priority" | "reserved" | "mode" | "push" | "pop" | "display" | "type"
+            )
+        }
+        _ => false,
+    };
+}
+
+fn parse_rule_attribute(group: &Group, attributes: &mut RuleAttributes) -> Result<(), Error> {
+    let tokens = group.stream().into_iter().collect::<Vec<_>>();
+    let name = match tokens.first() {
+        Some(TokenTree::Ident(identifier)) => identifier,
+        _ => return Err(Error::new(group.span(), "Invalid attribute.")),
+    };
+    let mut i = 0;
+
+    match name.to_string().as_str() {
+        "priority" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let mut token = next(&tokens, &mut i)?;
+            let mut sign = "";
+            if let TokenTree::Punct(punct) = token {
+                if punct.as_char() == '-' {
+                    sign = "-";
+                    token = next(&tokens, &mut i)?;
+                }
+            }
+            attributes.priority = match format!("{}{}", sign, token).parse::<i64>() {
+                Ok(priority) => Some(priority),
+                _ => return Err(Error::new(token.span(), "Invalid priority.")),
+            };
+
+            if let Some(token) = tokens.get(i + 1) {
+                return Err(Error::new(token.span(), "Invalid syntax."));
+            }
+        }
+        "reserved" => {
+            for token in parse_attribute_arguments(&tokens, name)? {
+                match &token {
+                    TokenTree::Literal(literal) => match parse_terminal_literal(literal)? {
+                        BNFSymbol::TerminalSymbolString(keyword) => {
+                            attributes.reserved.push(keyword)
+                        }
+                        _ => return Err(Error::new(token.span(), "Invalid reserved keyword.")),
+                    },
+                    _ => return Err(Error::new(token.span(), "Invalid reserved keyword.")),
+                }
+            }
+        }
+        "display" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let token = next(&tokens, &mut i)?;
+            attributes.display_name = match token {
+                TokenTree::Literal(literal) => match parse_terminal_literal(literal)? {
+                    BNFSymbol::TerminalSymbolString(display_name) => Some(display_name),
+                    _ => return Err(Error::new(token.span(), "Invalid display name.")),
+                },
+                _ => return Err(Error::new(token.span(), "Invalid display name.")),
+            };
+
+            if let Some(token) = tokens.get(i + 1) {
+                return Err(Error::new(token.span(), "Invalid syntax."));
+            }
+        }
+        "type" => {
+            check_next_punct(&tokens, &mut i, '=')?;
+
+            let value_type = tokens[(i + 1)..].iter().cloned().collect::<TokenStream>();
+            if value_type.is_empty() {
+                return Err(Error::new(group.span(), "A type must be specified."));
+            }
+            attributes.value_type = Some(value_type);
+        }
+        "mode" => {
+            for token in parse_attribute_arguments(&tokens, name)? {
+                match &token {
+                    TokenTree::Ident(mode) => attributes.modes.push(mode.to_string()),
+                    _ => return Err(Error::new(token.span(), "Invalid lexer mode.")),
+                }
+            }
+        }
+        "push" | "pop" => {
+            if attributes.transition.is_some() {
+                return Err(Error::new(
+                    name.span(),
+                    "Lexer mode transition is already specified.",
+                ));
+            }
+            let transition = if name.to_string() == "push" {
+                let arguments = parse_attribute_arguments(&tokens, name)?;
+                match arguments.as_slice() {
+                    [TokenTree::Ident(mode)] => ModeTransition::Push(mode.to_string()),
+                    _ => return Err(Error::new(group.span(), "Expected one lexer mode.")),
+                }
+            } else {
+                if let Some(token) = tokens.get(1) {
+                    return Err(Error::new(token.span(), "Invalid syntax."));
+                }
+                ModeTransition::Pop
+            };
+            attributes.transition = Some((transition, name.span()));
+        }
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                "Unknown attribute or the attribute must be placed before rules.",
+            ))
+        }
+    }
+
+    return Ok(());
+}
+
+fn parse_attribute_arguments(
+    tokens: &Vec<TokenTree>,
+    name: &Ident,
+) -> Result<Vec<TokenTree>, Error> {
+    let group = match tokens.get(1) {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => group,
+        _ => {
+            return Err(Error::new(
+                name.span(),
+                format!("'{}' requires arguments.", name),
+            ))
+        }
+    };
+
+    let mut arguments = Vec::new();
+    for token in group.stream() {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => {}
+            _ => arguments.push(token),
+        }
+    }
+
+    if arguments.is_empty() {
+        return Err(Error::new(
+            group.span(),
+            format!("'{}' requires arguments.", name),
+        ));
+    }
+    return Ok(arguments);
+}
+
+/// A symbol in precedence declarations or after '%prec'.<br>
+/// Identifiers name pseudo tokens which are only referred by '%prec'.
+fn parse_precedence_symbol(token: &TokenTree) -> Result<BNFSymbol, Error> {
+    return match token {
+        TokenTree::Ident(ident) => Ok(BNFSymbol::NonTerminalSymbolName(ident.to_string())),
+        TokenTree::Literal(literal) => parse_terminal_literal(literal),
+        _ => Err(Error::new(token.span(), "Invalid precedence symbol.")),
+    };
+}
+
+fn parse_terminal_literal(literal: &Literal) -> Result<BNFSymbol, Error> {
+    let string = match StringLit::try_from(literal) {
+        Ok(string) => string.value().to_string(),
+        Err(err) => {
+            return Err(Error::new(
+                literal.span(),
+                format!("Invalid terminal symbol. {}", err.to_string()),
+            ))
+        }
+    };
+
+    return if literal.to_string().starts_with("r") {
+        if let Err(err) = Regex::new(format!("^({})", string).as_str()) {
+            return Err(Error::new(
+                literal.span(),
+                format!("Invalid regex. {}", err),
+            ));
+        }
+        Ok(BNFSymbol::TerminalSymbolRegex(string))
+    } else {
+        Ok(BNFSymbol::TerminalSymbolString(string))
+    };
 }
 
 fn check_next_punct(tokens: &Vec<TokenTree>, i: &mut usize, char: char) -> Result<(), Error> {
@@ -141,26 +535,13 @@ fn check_next_punct(tokens: &Vec<TokenTree>, i: &mut usize, char: char) -> Resul
     };
 }
 
-fn check_current_identifier(
-    tokens: &Vec<TokenTree>,
-    i: &mut usize,
-    identifier_str: &str,
-) -> Result<(), Error> {
-    let token = current(tokens, i)?;
-
-    return match token {
-        TokenTree::Ident(identifier) => {
-            if identifier.to_string().as_str() == identifier_str {
-                Ok(())
-            } else {
-                Err(Error::new(
-                    identifier.span(),
-                    format!("Invalid syntax.{}", identifier.to_string()),
-                ))
-            }
-        }
-        _ => Err(Error::new(token.span(), "Invalid syntax.1")),
+/// Whether the token at 'i' is followed by '::='.
+fn is_rule_definition(tokens: &Vec<TokenTree>, i: usize) -> bool {
+    let is_punct = |index: usize, char: char| match tokens.get(index) {
+        Some(TokenTree::Punct(punct)) => punct.as_char() == char,
+        _ => false,
     };
+    return is_punct(i + 1, ':') && is_punct(i + 2, ':') && is_punct(i + 3, '=');
 }
 
 fn next<'a>(tokens: &'a Vec<TokenTree>, i: &mut usize) -> Result<&'a TokenTree, Error> {
@@ -174,27 +555,21 @@ fn next<'a>(tokens: &'a Vec<TokenTree>, i: &mut usize) -> Result<&'a TokenTree,
     return Ok(&tokens[*i]);
 }
 
-fn current<'a>(tokens: &'a Vec<TokenTree>, i: &usize) -> Result<&'a TokenTree, Error> {
-    if *i == tokens.len() {
-        return Err(Error::new(
-            tokens[tokens.len() - 1].span(),
-            "Unexpected EOF.",
-        ));
-    }
-    return Ok(&tokens[*i]);
-}
-
 fn parse_rule(
-    rule_map: &mut HashMap<String, BNFRule>,
+    context: &mut RuleContext,
     non_terminal_symbol_name: &mut String,
+    span: Span,
     tokens: &Vec<TokenTree>,
-    non_duplicate_number: &mut NonDuplicateNumber,
-    unnamed_pattern_map: &mut HashMap<Vec<Vec<BNFSymbol>>, String>,
+    attributes: &RuleAttributes,
 ) -> Result<(), Error> {
-    let mut rule = BNFRule::new(non_terminal_symbol_name.clone());
+    let mut rule = BNFRule::new(non_terminal_symbol_name.clone(), span);
 
     let mut pattern = Vec::<BNFSymbol>::new();
+    let mut pattern_info = PatternInfo::new(span);
     let or_patterns = &mut rule.or_patterns;
+    let pattern_infos = &mut rule.pattern_infos;
+    // the label for the next symbol
+    let mut label: Option<String> = None;
 
     let mut index = 0;
     loop {
@@ -202,20 +577,112 @@ fn parse_rule(
             break;
         }
         let token = &tokens[index];
+        let pattern_length = pattern.len();
 
         match token {
+            TokenTree::Ident(ident) if matches!(tokens.get(index + 1), Some(TokenTree::Punct(punct)) if punct.as_char() == ':') =>
+            {
+                // label:symbol
+                let is_symbol = match tokens.get(index + 2) {
+                    Some(TokenTree::Ident(_)) => !matches!(
+                        tokens.get(index + 3),
+                        Some(TokenTree::Punct(punct)) if punct.as_char() == ':'
+                    ),
+                    Some(TokenTree::Literal(_)) | Some(TokenTree::Group(_)) => true,
+                    _ => false,
+                };
+                if !is_symbol {
+                    return Err(Error::new(
+                        ident.span(),
+                        "A label must be followed by a symbol.",
+                    ));
+                }
+
+                label = Some(ident.to_string());
+                index += 2;
+                continue;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '%' => {
+                // pattern %prec symbol
+                let mut i = index;
+                match next(tokens, &mut i)? {
+                    TokenTree::Ident(ident) if ident.to_string() == "prec" => {}
+                    token => return Err(Error::new(token.span(), "Invalid syntax.")),
+                }
+                let token = next(tokens, &mut i)?;
+                let symbol = parse_precedence_symbol(token)?;
+
+                if !context.settings.precedence_map.contains_key(&symbol) {
+                    return Err(Error::new(
+                        token.span(),
+                        "Precedence is not declared for this symbol.",
+                    ));
+                }
+                if pattern_info.precedence.is_some() {
+                    return Err(Error::new(punct.span(), "Duplicate '%prec'."));
+                }
+                match tokens.get(i + 1) {
+                    Some(TokenTree::Punct(punct))
+                        if punct.as_char() == '|' || punct.as_char() == '=' => {}
+                    None => {}
+                    Some(_) => {
+                        return Err(Error::new(
+                            punct.span(),
+                            "'%prec' must be placed at the end of a pattern.",
+                        ))
+                    }
+                }
+
+                pattern_info.precedence = Some(symbol);
+                index = i;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '=' => {
+                // pattern => { action }
+                let mut i = index;
+                check_next_punct(tokens, &mut i, '>')?;
+                let action = match next(tokens, &mut i)? {
+                    TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => group,
+                    token => return Err(Error::new(token.span(), "An action must be a block.")),
+                };
+
+                if non_terminal_symbol_name.starts_with(' ') {
+                    return Err(Error::new(
+                        punct.span(),
+                        "Actions are only available in patterns of named rules.",
+                    ));
+                }
+                match tokens.get(i + 1) {
+                    Some(TokenTree::Punct(punct)) if punct.as_char() == '|' => {}
+                    None => {}
+                    Some(_) => {
+                        return Err(Error::new(
+                            punct.span(),
+                            "An action must be placed at the end of a pattern.",
+                        ))
+                    }
+                }
+
+                pattern_info.action = Some(action.clone());
+                index = i;
+            }
             TokenTree::Punct(punct) => {
                 if punct.as_char() != '|' {
                     return Err(Error::new(punct.span(), "Invalid punctuation."));
                 }
                 if pattern.is_empty() {
-                    pattern.push(BNFSymbol::Null);
+                    push_symbol(
+                        &mut pattern,
+                        &mut pattern_info,
+                        BNFSymbol::Null,
+                        punct.span(),
+                    );
                 }
 
                 let mut pattern_temp = Vec::new();
                 mem::swap(&mut pattern_temp, &mut pattern);
 
                 or_patterns.push(pattern_temp);
+                pattern_infos.push(mem::replace(&mut pattern_info, PatternInfo::new(span)));
             }
             TokenTree::Ident(ident) => {
                 if ident.to_string() == "fn" {
@@ -238,95 +705,111 @@ fn parse_rule(
                         let func_string = ident_chars[1..(ident_chars.len() - 1)]
                             .iter()
                             .collect::<String>();
-                        pattern.push(BNFSymbol::TerminalSymbolFunction(func_string));
+                        let symbol = BNFSymbol::TerminalSymbolFunction(func_string);
+                        context.settings.declare_terminal_symbol(
+                            &symbol,
+                            ident.span(),
+                            attributes,
+                        )?;
+                        push_symbol(&mut pattern, &mut pattern_info, symbol, ident.span());
 
                         index = next_index;
                     } else {
                         return Err(Error::new(ident.span(), "A function must be specified."));
                     }
                 } else {
-                    pattern.push(BNFSymbol::NonTerminalSymbolName(ident.to_string()));
+                    let name = ident.to_string();
+                    let symbol =
+                        if context.settings.layout && LAYOUT_SYMBOLS.contains(&name.as_str()) {
+                            BNFSymbol::TerminalSymbolLayout(name)
+                        } else if name == ERROR_SYMBOL_NAME {
+                            BNFSymbol::TerminalSymbolError
+                        } else {
+                            BNFSymbol::NonTerminalSymbolName(name)
+                        };
+                    push_symbol(&mut pattern, &mut pattern_info, symbol, ident.span());
                 }
             }
             TokenTree::Group(group) => {
                 let delimiter = group.delimiter();
                 let symbols = group.stream().into_iter().collect::<Vec<TokenTree>>();
-                let mut new_symbol_name = non_duplicate_number.as_symbol_name();
+                let mut new_symbol_name = context.non_duplicate_number.as_symbol_name();
 
                 match delimiter {
                     Delimiter::Parenthesis => {
                         parse_rule(
-                            rule_map,
+                            context,
                             &mut new_symbol_name,
+                            group.span(),
                             &symbols,
-                            non_duplicate_number,
-                            unnamed_pattern_map,
+                            attributes,
                         )?;
                     }
                     Delimiter::Brace => {
                         // new_symbol ::= null | new_pattern new_symbol
-                        let mut new_pattern_name = non_duplicate_number.as_symbol_name();
+                        let mut new_pattern_name = context.non_duplicate_number.as_symbol_name();
                         parse_rule(
-                            rule_map,
+                            context,
                             &mut new_pattern_name,
+                            group.span(),
                             &symbols,
-                            non_duplicate_number,
-                            unnamed_pattern_map,
+                            attributes,
                         )?;
 
-                        let mut rule = BNFRule::new(new_symbol_name.clone());
-                        let or_patterns = &mut rule.or_patterns;
+                        let mut rule = BNFRule::new(new_symbol_name.clone(), group.span());
+                        rule.kind = BNFRuleKind::Repetition;
 
-                        or_patterns.push(vec![BNFSymbol::Null]);
-                        or_patterns.push(vec![
+                        rule.push_pattern(vec![BNFSymbol::Null]);
+                        rule.push_pattern(vec![
                             BNFSymbol::NonTerminalSymbolName(new_pattern_name),
                             BNFSymbol::NonTerminalSymbolName(new_symbol_name.clone()),
                         ]);
 
-                        rule_map.insert(rule.non_terminal_symbol_name.clone(), rule);
+                        insert_rule(&mut context.rule_map, rule);
                     }
                     Delimiter::Bracket => {
                         // new_symbol ::= new_pattern | null
-                        let mut new_pattern_name = non_duplicate_number.as_symbol_name();
+                        let mut new_pattern_name = context.non_duplicate_number.as_symbol_name();
                         parse_rule(
-                            rule_map,
+                            context,
                             &mut new_pattern_name,
+                            group.span(),
                             &symbols,
-                            non_duplicate_number,
-                            unnamed_pattern_map,
+                            attributes,
                         )?;
 
-                        let mut rule = BNFRule::new(new_symbol_name.clone());
-                        let or_patterns = &mut rule.or_patterns;
+                        let mut rule = BNFRule::new(new_symbol_name.clone(), group.span());
+                        rule.kind = BNFRuleKind::Optional;
 
-                        or_patterns.push(vec![BNFSymbol::NonTerminalSymbolName(new_pattern_name)]);
-                        or_patterns.push(vec![BNFSymbol::Null]);
+                        rule.push_pattern(vec![BNFSymbol::NonTerminalSymbolName(new_pattern_name)]);
+                        rule.push_pattern(vec![BNFSymbol::Null]);
 
-                        rule_map.insert(rule.non_terminal_symbol_name.clone(), rule);
+                        insert_rule(&mut context.rule_map, rule);
                     }
                     _ => {
                         return Err(Error::new(group.span(), "Invalid delimiter."));
                     }
                 }
 
-                pattern.push(BNFSymbol::NonTerminalSymbolName(new_symbol_name.clone()));
+                push_symbol(
+                    &mut pattern,
+                    &mut pattern_info,
+                    BNFSymbol::NonTerminalSymbolName(new_symbol_name.clone()),
+                    group.span(),
+                );
             }
             TokenTree::Literal(literal) => {
-                let string = match StringLit::try_from(literal) {
-                    Ok(string) => string.value().to_string(),
-                    Err(err) => {
-                        return Err(Error::new(
-                            literal.span(),
-                            format!("Invalid terminal symbol. {}", err.to_string()),
-                        ))
-                    }
-                };
+                let symbol = parse_terminal_literal(literal)?;
+                context
+                    .settings
+                    .declare_terminal_symbol(&symbol, literal.span(), attributes)?;
+                push_symbol(&mut pattern, &mut pattern_info, symbol, literal.span());
+            }
+        }
 
-                if literal.to_string().starts_with("r") {
-                    pattern.push(BNFSymbol::TerminalSymbolRegex(string));
-                } else {
-                    pattern.push(BNFSymbol::TerminalSymbolString(string));
-                }
+        if pattern.len() > pattern_length {
+            if let Some(label) = label.take() {
+                *pattern_info.labels.last_mut().unwrap() = Some(label);
             }
         }
 
@@ -335,29 +818,121 @@ fn parse_rule(
 
     if !pattern.is_empty() {
         or_patterns.push(pattern);
+        pattern_infos.push(pattern_info);
+    } else if pattern_info.precedence.is_some() || pattern_info.action.is_some() {
+        push_symbol(&mut pattern, &mut pattern_info, BNFSymbol::Null, span);
+        or_patterns.push(pattern);
+        pattern_infos.push(pattern_info);
     }
 
     // merge unnamed pattern
     if non_terminal_symbol_name.starts_with(' ') {
-        match unnamed_pattern_map.get(or_patterns) {
+        let key = (
+            or_patterns.clone(),
+            pattern_infos
+                .iter()
+                .map(|pattern_info| pattern_info.labels.clone())
+                .collect::<Vec<_>>(),
+        );
+        match context.unnamed_pattern_map.get(&key) {
             Some(temp_name) => {
                 *non_terminal_symbol_name = temp_name.clone();
             }
             _ => {
-                unnamed_pattern_map.insert(or_patterns.clone(), non_terminal_symbol_name.clone());
+                context
+                    .unnamed_pattern_map
+                    .insert(key, non_terminal_symbol_name.clone());
 
                 rule.non_terminal_symbol_name = non_terminal_symbol_name.clone();
-                rule_map.insert(non_terminal_symbol_name.clone(), rule);
+                insert_rule(&mut context.rule_map, rule);
             }
         }
     } else {
+        if non_terminal_symbol_name == ERROR_SYMBOL_NAME {
+            return Err(Error::new(
+                span,
+                format!("'{}' is reserved for error productions.", ERROR_SYMBOL_NAME),
+            ));
+        }
+        if let Some(defined_rule) = context.rule_map.get(non_terminal_symbol_name.as_str()) {
+            let mut error = Error::new(
+                span,
+                format!(
+                    "Rule '{}' is defined more than once.",
+                    non_terminal_symbol_name
+                ),
+            );
+            error.combine(Error::new(
+                defined_rule.span,
+                format!("'{}' is first defined here.", non_terminal_symbol_name),
+            ));
+            return Err(error);
+        }
+
         rule.non_terminal_symbol_name = non_terminal_symbol_name.clone();
-        rule_map.insert(non_terminal_symbol_name.clone(), rule);
+        rule.value_type = attributes.value_type.clone();
+        insert_rule(&mut context.rule_map, rule);
     }
 
     return Ok(());
 }
 
+/// Patterns and labels of an unnamed rule, which is shared by the same groups.
+type UnnamedPatternKey = (Vec<Vec<BNFSymbol>>, Vec<Vec<Option<String>>>);
+
+/// Rules and settings which 'parse_rule' builds for all rules and groups of the grammar.
+struct RuleContext {
+    rule_map: HashMap<String, BNFRule>,
+    non_duplicate_number: NonDuplicateNumber,
+    unnamed_pattern_map: HashMap<UnnamedPatternKey, String>,
+    settings: GrammarSettings,
+}
+
+impl RuleContext {
+    fn new() -> Self {
+        return Self {
+            rule_map: HashMap::new(),
+            non_duplicate_number: NonDuplicateNumber::new(),
+            unnamed_pattern_map: HashMap::new(),
+            settings: GrammarSettings::new(),
+        };
+    }
+}
+
+fn insert_rule(rule_map: &mut HashMap<String, BNFRule>, mut rule: BNFRule) {
+    rule.order = rule_map.len();
+    rule_map.insert(rule.non_terminal_symbol_name.clone(), rule);
+}
+
+/// Rules in order of definition, to make the generated parser reproducible.
+pub(crate) fn sorted_rules(rule_map: &HashMap<String, BNFRule>) -> Vec<&BNFRule> {
+    let mut rules = rule_map.values().collect::<Vec<_>>();
+    rules.sort_by_key(|rule| rule.order);
+    return rules;
+}
+
+fn push_symbol(
+    pattern: &mut Vec<BNFSymbol>,
+    pattern_info: &mut PatternInfo,
+    symbol: BNFSymbol,
+    span: Span,
+) {
+    if pattern.is_empty() {
+        pattern_info.span = span;
+    }
+    pattern.push(symbol);
+    pattern_info.symbol_spans.push(span);
+    pattern_info.labels.push(None);
+}
+
+/// Combine errors into one error which emits all of them.
+pub fn combine_errors(errors: Vec<Error>) -> Option<Error> {
+    return errors.into_iter().reduce(|mut error, other| {
+        error.combine(other);
+        error
+    });
+}
+
 #[derive()]
 pub struct TokenParser {
     pub symbols: Vec<TokenTree>,
@@ -368,7 +943,7 @@ impl Parse for TokenParser {
         let mut symbols = Vec::<TokenTree>::new();
 
         while !input.is_empty() {
-            let token_tree = TokenTree::parse(input).unwrap();
+            let token_tree = TokenTree::parse(input)?;
 
             symbols.push(token_tree);
         }
@@ -380,20 +955,376 @@ impl Parse for TokenParser {
 #[derive(Debug)]
 pub struct BNFRule {
     pub non_terminal_symbol_name: String,
+    pub span: Span,
+    pub kind: BNFRuleKind,
     pub or_patterns: Vec<Vec<BNFSymbol>>,
+    /// Additional information for each pattern of 'or_patterns'.
+    pub pattern_infos: Vec<PatternInfo>,
     pub first_set: HashSet<BNFSymbol>,
     pub is_nullable: bool,
+    /// The index in order of definition, which decides ids and state numbers.
+    pub order: usize,
+    /// Type of values of semantic actions, specified by '#[type = ..]'.
+    pub value_type: Option<TokenStream>,
 }
 
 impl BNFRule {
-    pub fn new(non_terminal_symbol_name: String) -> Self {
+    pub fn new(non_terminal_symbol_name: String, span: Span) -> Self {
+        let kind = if non_terminal_symbol_name.starts_with(' ') {
+            BNFRuleKind::Group
+        } else {
+            BNFRuleKind::Named
+        };
+
         return Self {
             non_terminal_symbol_name,
+            span,
+            kind,
             or_patterns: Vec::new(),
+            pattern_infos: Vec::new(),
             first_set: HashSet::new(),
             is_nullable: false,
+            order: 0,
+            value_type: None,
         };
     }
+
+    /// Add a pattern which is made internally, all symbols share the span of the rule.
+    pub fn push_pattern(&mut self, pattern: Vec<BNFSymbol>) {
+        let mut pattern_info = PatternInfo::new(self.span);
+        pattern_info.symbol_spans = vec![self.span; pattern.len()];
+        pattern_info.labels = vec![None; pattern.len()];
+
+        self.or_patterns.push(pattern);
+        self.pattern_infos.push(pattern_info);
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BNFRuleKind {
+    /// A rule which is defined by the user.
+    Named,
+    /// An unnamed rule made by '( )', or contents of '{ }' and '[ ]'.
+    Group,
+    /// An unnamed rule made by '[ ]'.
+    Optional,
+    /// An unnamed rule made by '{ }'.
+    Repetition,
+    /// An augmented rule ' entry ::= entry' for each entry point.
+    Start,
+}
+
+#[derive(Debug, Clone)]
+pub struct PatternInfo {
+    /// A symbol specified by '%prec'.
+    pub precedence: Option<BNFSymbol>,
+    /// The span of the first symbol in the pattern.
+    pub span: Span,
+    /// The spans of each symbol in the pattern.
+    pub symbol_spans: Vec<Span>,
+    /// A semantic action '=> { .. }', which computes the value of the pattern.
+    pub action: Option<Group>,
+    /// Labels of each symbol in the pattern, e.g. 'lhs' of 'lhs:expr'.
+    pub labels: Vec<Option<String>>,
+}
+
+impl PatternInfo {
+    pub fn new(span: Span) -> Self {
+        return Self {
+            precedence: None,
+            span,
+            symbol_spans: Vec::new(),
+            action: None,
+            labels: Vec::new(),
+        };
+    }
+}
+
+#[derive(Debug)]
+pub struct GrammarSettings {
+    pub generate_code: bool,
+    pub precedence_map: HashMap<BNFSymbol, Precedence>,
+    /// Rules specified by '#[entry(..)]', 'parse_{rule}' is generated for each.
+    pub entries: Vec<(String, Span)>,
+    /// Terminal symbols specified by '#[skip(..)]', which are ignored by the lexer.
+    pub skip_symbols: Vec<BNFSymbol>,
+    /// Whether the lexer skips whitespaces by default.
+    pub skip_default: bool,
+    /// Terminal symbols specified by '#[sync(..)]', where the error recovery restarts parsing.
+    pub sync_symbols: Vec<(BNFSymbol, Span)>,
+    /// Lexer settings of all terminal symbols which appear in rules.
+    pub terminal_symbol_map: HashMap<BNFSymbol, TerminalSymbolSettings>,
+    /// Specified by '#[table = lr1 | pager | lalr]'.
+    pub table_mode: TableMode,
+    /// Whether the parse table is compressed, '#[compress_table = false]' is for debugging.
+    pub compress_table: bool,
+    /// Whether a compressed table reduces without the lookahead in states which only reduce by one pattern,
+    /// specified by '#[default_reductions = true]'. Syntax errors are detected later.
+    pub default_reductions: bool,
+    /// Specified by '#[scan = batch | on_demand]'.
+    pub scan_mode: ScanMode,
+    /// Whether the lexer makes 'INDENT', 'DEDENT' and 'NEWLINE' tokens, specified by '#[layout = true]'.
+    pub layout: bool,
+    /// Whether a struct or an enum is generated for each rule, specified by '#[typed_ast = true]'.
+    pub typed_ast: bool,
+    /// Names of lexer modes in order of appearance, the first is the default mode.
+    pub lexer_modes: Vec<String>,
+    /// Modes specified by '#[push(..)]', which must have terminal symbols.
+    pushed_modes: Vec<(String, Span)>,
+}
+
+impl GrammarSettings {
+    pub fn new() -> Self {
+        return Self {
+            generate_code: true,
+            precedence_map: HashMap::new(),
+            entries: Vec::new(),
+            skip_symbols: Vec::new(),
+            skip_default: true,
+            sync_symbols: Vec::new(),
+            terminal_symbol_map: HashMap::new(),
+            table_mode: TableMode::Canonical,
+            compress_table: true,
+            default_reductions: false,
+            scan_mode: ScanMode::Batch,
+            layout: false,
+            typed_ast: false,
+            lexer_modes: vec![DEFAULT_LEXER_MODE.to_string()],
+            pushed_modes: Vec::new(),
+        };
+    }
+
+    fn declare_terminal_symbol(
+        &mut self,
+        symbol: &BNFSymbol,
+        span: Span,
+        attributes: &RuleAttributes,
+    ) -> Result<(), Error> {
+        let order = self.terminal_symbol_map.len();
+        let symbol_settings = self
+            .terminal_symbol_map
+            .entry(symbol.clone())
+            .or_insert_with(|| TerminalSymbolSettings::new(order));
+
+        if let Some(priority) = attributes.priority {
+            match symbol_settings.priority {
+                Some(declared) if declared != priority => {
+                    return Err(Error::new(
+                        span,
+                        format!(
+                            "Different priority is already declared for this symbol : {}",
+                            declared
+                        ),
+                    ));
+                }
+                _ => symbol_settings.priority = Some(priority),
+            }
+        }
+
+        let default_modes = [DEFAULT_LEXER_MODE.to_string()];
+        let modes = if attributes.modes.is_empty() {
+            &default_modes[..]
+        } else {
+            &attributes.modes[..]
+        };
+        let transition = attributes
+            .transition
+            .as_ref()
+            .map(|(transition, _)| transition.clone());
+        for mode in modes.iter() {
+            match symbol_settings
+                .modes
+                .iter()
+                .find(|(other, _)| other == mode)
+            {
+                Some((_, declared)) if *declared != transition => {
+                    return Err(Error::new(
+                        span,
+                        format!(
+                            "Different lexer mode transition is already declared for this symbol in the mode '{}'.",
+                            mode
+                        ),
+                    ));
+                }
+                Some(_) => {}
+                _ => symbol_settings
+                    .modes
+                    .push((mode.clone(), transition.clone())),
+            }
+            if !self.lexer_modes.contains(mode) {
+                self.lexer_modes.push(mode.clone());
+            }
+        }
+        if let Some((ModeTransition::Push(mode), span)) = &attributes.transition {
+            self.pushed_modes.push((mode.clone(), *span));
+        }
+
+        // keywords are excluded from regexes and functions
+        if let BNFSymbol::TerminalSymbolString(_) = symbol {
+            return Ok(());
+        }
+        if let Some(display_name) = &attributes.display_name {
+            match &symbol_settings.display_name {
+                Some(declared) if declared != display_name => {
+                    return Err(Error::new(
+                        span,
+                        format!(
+                            "Different display name is already declared for this symbol : {}",
+                            declared
+                        ),
+                    ));
+                }
+                _ => symbol_settings.display_name = Some(display_name.clone()),
+            }
+        }
+        for keyword in attributes.reserved.iter() {
+            if !symbol_settings.reserved.contains(keyword) {
+                symbol_settings.reserved.push(keyword.clone());
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn check_lexer_modes(&self) -> Result<(), Error> {
+        for (mode, span) in self.pushed_modes.iter() {
+            if !self.lexer_modes.contains(mode) {
+                return Err(Error::new(
+                    *span,
+                    format!("Lexer mode '{}' has no terminal symbols.", mode),
+                ));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Terminal symbols which match the same length are tried in this order.<br>
+    /// Higher priority first, then literal strings before others, then declaration order.
+    pub fn get_lexer_order(&self, symbol: &BNFSymbol) -> (i64, bool, usize) {
+        let is_string = matches!(symbol, BNFSymbol::TerminalSymbolString(_));
+        return match self.terminal_symbol_map.get(symbol) {
+            Some(settings) => (-settings.priority.unwrap_or(0), !is_string, settings.order),
+            _ => (0, !is_string, usize::MAX),
+        };
+    }
+}
+
+impl Default for GrammarSettings {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// How LR states which have the same core are merged.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum TableMode {
+    /// Canonical LR(1), which never merges states with different lookaheads.
+    Canonical,
+    /// Pager's method, which merges states unless it makes new conflicts.
+    Pager,
+    /// LALR(1), which always merges states.
+    LALR,
+}
+
+/// When the lexer reads tokens.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ScanMode {
+    /// All tokens are scanned before parsing.
+    Batch,
+    /// Each token is scanned when the parser needs it,
+    /// only with terminal symbols which are acceptable in the current state.
+    OnDemand,
+}
+
+/// Attributes placed before a rule, which affect the rule and terminal symbols in the rule.
+#[derive(Debug, Clone)]
+pub struct RuleAttributes {
+    /// Specified by '#[priority = N]'.
+    pub priority: Option<i64>,
+    /// Keywords specified by '#[reserved(..)]'.
+    pub reserved: Vec<String>,
+    /// Lexer modes specified by '#[mode(..)]', the default mode if empty.
+    pub modes: Vec<String>,
+    /// Specified by '#[push(mode)]' or '#[pop]'.
+    pub transition: Option<(ModeTransition, Span)>,
+    /// Name of regexes and functions in error messages, specified by '#[display = "name"]'.
+    pub display_name: Option<String>,
+    /// Type of values of semantic actions, specified by '#[type = ..]'.
+    pub value_type: Option<TokenStream>,
+}
+
+impl RuleAttributes {
+    pub fn new() -> Self {
+        return Self {
+            priority: None,
+            reserved: Vec::new(),
+            modes: Vec::new(),
+            transition: None,
+            display_name: None,
+            value_type: None,
+        };
+    }
+}
+
+impl Default for RuleAttributes {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Terminal symbols made by the lexer from line breaks and indentation, in this order.
+pub const LAYOUT_SYMBOLS: [&str; 3] = ["INDENT", "DEDENT", "NEWLINE"];
+
+/// Name of the terminal symbol for error productions, e.g. 'statement ::= error ";"'.
+pub const ERROR_SYMBOL_NAME: &str = "error";
+
+/// Name of the lexer mode which is used first and has terminal symbols without '#[mode(..)]'.
+pub const DEFAULT_LEXER_MODE: &str = "default";
+
+/// Change of the lexer mode stack when a terminal symbol is matched.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ModeTransition {
+    Push(String),
+    Pop,
+}
+
+#[derive(Debug, Clone)]
+pub struct TerminalSymbolSettings {
+    /// The index in order of appearance.
+    pub order: usize,
+    pub priority: Option<i64>,
+    /// Texts which are never matched by the regex or function.
+    pub reserved: Vec<String>,
+    /// Lexer modes which the symbol belongs to, with the transition in each mode.
+    pub modes: Vec<(String, Option<ModeTransition>)>,
+    /// Name in error messages instead of the regex or the function.
+    pub display_name: Option<String>,
+}
+
+impl TerminalSymbolSettings {
+    pub fn new(order: usize) -> Self {
+        return Self {
+            order,
+            priority: None,
+            reserved: Vec::new(),
+            modes: Vec::new(),
+            display_name: None,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Precedence {
+    /// The higher level binds tighter.
+    pub level: usize,
+    pub associativity: Associativity,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Associativity {
+    Left,
+    Right,
+    NonAssoc,
 }
 
 #[derive(Debug, Eq, Clone, Hash, PartialEq)]
@@ -402,6 +1333,10 @@ pub enum BNFSymbol {
     TerminalSymbolString(String),
     TerminalSymbolRegex(String),
     TerminalSymbolFunction(String),
+    /// 'INDENT', 'DEDENT' or 'NEWLINE' made by the lexer, specified by '#[layout = true]'.
+    TerminalSymbolLayout(String),
+    /// 'error' of error productions, which stands for input skipped by the error recovery.
+    TerminalSymbolError,
     Null,
     EOF,
 }
@@ -420,6 +1355,8 @@ impl BNFSymbol {
             BNFSymbol::NonTerminalSymbolName(name) => name.as_str(),
             BNFSymbol::TerminalSymbolString(name) => name.as_str(),
             BNFSymbol::TerminalSymbolRegex(name) => name.as_str(),
+            BNFSymbol::TerminalSymbolLayout(name) => name.as_str(),
+            BNFSymbol::TerminalSymbolError => ERROR_SYMBOL_NAME,
             BNFSymbol::Null => "Null",
             BNFSymbol::EOF => "EOF",
         };
@@ -449,20 +1386,44 @@ pub struct ParserGenerator {
     rule_map: HashMap<String, BNFRule>,
     single_pattern_rules: Vec<SinglePatternRule>,
     symbol_id_map: HashMap<BNFSymbol, usize>,
+    /// Symbols in order of ids.
+    symbol_list: Vec<BNFSymbol>,
+    /// Ids of patterns for each rule.
+    rule_pattern_map: HashMap<String, Vec<usize>>,
+    /// First sets of the rest of each pattern after each position, and whether it is nullable.
+    latter_first_sets: Vec<Vec<(SymbolSet, bool)>>,
+    /// Names of entry rules, the index is the number of its start group.
+    entries: Vec<String>,
+    settings: GrammarSettings,
+    warnings: Vec<LintWarning>,
 }
 
 impl ParserGenerator {
-    pub fn new(mut rule_map: HashMap<String, BNFRule>) -> Self {
-        let mut source_rule = BNFRule::new(" source".to_string());
-        source_rule
-            .or_patterns
-            .push(vec![BNFSymbol::NonTerminalSymbolName("source".to_string())]);
+    pub fn new(mut rule_map: HashMap<String, BNFRule>, settings: GrammarSettings) -> Self {
+        // 'source' is always an entry point if it is defined
+        let mut entries = Vec::<(String, Span)>::new();
+        if settings.entries.is_empty() || rule_map.contains_key("source") {
+            entries.push(("source".to_string(), Span::call_site()));
+        }
+        for entry in settings.entries.iter() {
+            if entry.0 != "source" {
+                entries.push(entry.clone());
+            }
+        }
+
+        // augmented start rules, ' entry ::= entry'
+        for (entry, span) in entries.iter() {
+            let mut start_rule = BNFRule::new(format!(" {}", entry), *span);
+            start_rule.kind = BNFRuleKind::Start;
+            start_rule.push_pattern(vec![BNFSymbol::NonTerminalSymbolName(entry.clone())]);
 
-        rule_map.insert(" source".to_string(), source_rule);
+            insert_rule(&mut rule_map, start_rule);
+        }
+        let entries = entries.into_iter().map(|entry| entry.0).collect::<Vec<_>>();
 
         let mut single_pattern_rules = Vec::<SinglePatternRule>::new();
-        for rule in rule_map.values() {
-            for pattern in rule.or_patterns.iter() {
+        for rule in sorted_rules(&rule_map) {
+            for (pattern, pattern_info) in rule.or_patterns.iter().zip(rule.pattern_infos.iter()) {
                 let mut new_pattern = Vec::<BNFSymbol>::new();
                 for symbol in pattern.iter() {
                     match symbol {
@@ -472,9 +1433,24 @@ impl ParserGenerator {
                     }
                 }
 
+                // '%prec' or the last terminal symbol decides the precedence, as in yacc.
+                let precedence_symbol = match &pattern_info.precedence {
+                    Some(symbol) => Some(symbol),
+                    _ => new_pattern
+                        .iter()
+                        .rev()
+                        .find(|symbol| symbol.is_terminal_symbol()),
+                };
+                let precedence = match precedence_symbol {
+                    Some(symbol) => settings.precedence_map.get(symbol).cloned(),
+                    _ => None,
+                };
+
                 single_pattern_rules.push(SinglePatternRule::new(
                     rule.non_terminal_symbol_name.clone(),
                     new_pattern,
+                    precedence,
+                    pattern_info.span,
                 ));
             }
         }
@@ -485,7 +1461,15 @@ impl ParserGenerator {
         symbol_id_map.insert(BNFSymbol::EOF, last_id);
         last_id += 1;
 
-        for rule in rule_map.values() {
+        for rule in sorted_rules(&rule_map) {
+            // rules which are never referred also need ids
+            let rule_symbol =
+                BNFSymbol::NonTerminalSymbolName(rule.non_terminal_symbol_name.clone());
+            if rule.kind != BNFRuleKind::Start && !symbol_id_map.contains_key(&rule_symbol) {
+                symbol_id_map.insert(rule_symbol, last_id);
+                last_id += 1;
+            }
+
             for pattern in rule.or_patterns.iter() {
                 for symbol in pattern.iter() {
                     match symbol {
@@ -502,21 +1486,62 @@ impl ParserGenerator {
             }
         }
 
-        symbol_id_map.insert(
-            BNFSymbol::NonTerminalSymbolName(" source".to_string()),
-            last_id,
-        );
+        // the lexer makes all layout symbols even if some of them are not referred
+        if settings.layout {
+            for name in LAYOUT_SYMBOLS {
+                let symbol = BNFSymbol::TerminalSymbolLayout(name.to_string());
+                if let Entry::Vacant(entry) = symbol_id_map.entry(symbol) {
+                    entry.insert(last_id);
+                    last_id += 1;
+                }
+            }
+        }
+
+        for entry in entries.iter() {
+            symbol_id_map.insert(
+                BNFSymbol::NonTerminalSymbolName(format!(" {}", entry)),
+                last_id,
+            );
+            last_id += 1;
+        }
+
+        let mut symbol_list = vec![BNFSymbol::Null; symbol_id_map.len()];
+        for (symbol, id) in symbol_id_map.iter() {
+            symbol_list[*id] = symbol.clone();
+        }
+
+        let mut rule_pattern_map = HashMap::<String, Vec<usize>>::new();
+        for (pattern_id, rule) in single_pattern_rules.iter().enumerate() {
+            rule_pattern_map
+                .entry(rule.root_symbol_name.clone())
+                .or_default()
+                .push(pattern_id);
+        }
 
         return Self {
             rule_map,
             single_pattern_rules,
             symbol_id_map,
+            symbol_list,
+            rule_pattern_map,
+            latter_first_sets: Vec::new(),
+            entries,
+            settings,
+            warnings: Vec::new(),
         };
     }
 
-    pub fn generate(&mut self, generate_code: bool) -> Result<String, String> {
+    /// Warnings which are found by 'generate()'.
+    pub fn get_warnings(&self) -> &Vec<LintWarning> {
+        return &self.warnings;
+    }
+
+    pub fn generate(&mut self) -> Result<String, Error> {
+        self.warnings = check_rules(&self.rule_map)?;
+        self.check_sync_symbols()?;
         self.search_nulls_and_first_set();
-        return Ok(self.generate_parser(generate_code)?);
+        self.search_latter_first_sets();
+        return Ok(self.generate_parser()?);
     }
 
     fn search_nulls_and_first_set(&mut self) {
@@ -627,7 +1652,9 @@ impl ParserGenerator {
                             }
                             BNFSymbol::TerminalSymbolString(_)
                             | BNFSymbol::TerminalSymbolFunction(_)
-                            | BNFSymbol::TerminalSymbolRegex(_) => {
+                            | BNFSymbol::TerminalSymbolRegex(_)
+                            | BNFSymbol::TerminalSymbolLayout(_)
+                            | BNFSymbol::TerminalSymbolError => {
                                 if !first_set.contains(symbol) {
                                     first_set_add.insert(symbol.clone());
                                     retry = true;
@@ -672,116 +1699,125 @@ impl ParserGenerator {
         }
     }
 
-    fn generate_parser(&self, generate_code: bool) -> Result<String, String> {
+    fn generate_parser(&self) -> Result<String, Error> {
         let mut lr_group_map = HashMap::<usize, LRGroup>::new();
         let mut not_scanned_group_list = Vec::<usize>::new();
         let mut last_group_number = 0;
 
-        loop {
-            let lr_group_number = if last_group_number == 0 {
-                let mut first_group = LRGroup::new(0);
+        // groups are found by hashed kernels, instead of comparing items
+        let mut kernel_map = HashMap::<Vec<LRItem>, usize>::new();
+        let mut core_map = HashMap::<Vec<(usize, usize)>, Vec<usize>>::new();
 
-                let source_first_rule = self.rule_map.get(" source").unwrap();
+        // start groups of all entries share one automaton
+        for entry in self.entries.iter() {
+            let start_rule_name = format!(" {}", entry);
 
-                for pattern in source_first_rule.or_patterns.iter() {
-                    let mut item = LRItem::new(" source".to_string());
-                    item.pattern.extend(pattern.clone());
-                    item.first_set.insert(BNFSymbol::EOF);
-                    first_group.default_item_list.push(item.clone());
-                    first_group.item_list.push(item);
+            let mut kernel = Vec::<LRItem>::new();
+            for (pattern_id, rule) in self.single_pattern_rules.iter().enumerate() {
+                if rule.root_symbol_name == start_rule_name {
+                    let mut first_set = SymbolSet::new(self.symbol_list.len());
+                    first_set.insert(self.symbol_id_map[&BNFSymbol::EOF]);
+                    kernel.push(LRItem::new(pattern_id, 0, first_set));
                 }
+            }
 
-                self.add_items(&mut first_group);
+            let mut start_group = LRGroup::new(last_group_number);
+            start_group.default_item_list = kernel.clone();
+            start_group.item_list = kernel.clone();
+            self.add_items(&mut start_group);
+
+            core_map
+                .entry(get_kernel_core(&kernel))
+                .or_default()
+                .push(last_group_number);
+            kernel_map.insert(kernel, last_group_number);
+            lr_group_map.insert(last_group_number, start_group);
+            not_scanned_group_list.push(last_group_number);
+            last_group_number += 1;
+        }
 
-                lr_group_map.insert(0, first_group);
-                last_group_number += 1;
-                0
-            } else {
-                match not_scanned_group_list.pop() {
-                    Some(number) => number,
-                    _ => break,
-                }
+        loop {
+            let lr_group_number = match not_scanned_group_list.pop() {
+                Some(number) => number,
+                _ => break,
             };
 
             let lr_group = lr_group_map.get(&lr_group_number).unwrap();
 
-            let mut next_group_map = HashMap::<BNFSymbol, Vec<&LRItem>>::new();
+            // in order of appearance in the items, to number groups reproducibly
+            let mut next_group_map = Vec::<(BNFSymbol, Vec<LRItem>)>::new();
+            let mut next_symbol_index_map = HashMap::<&BNFSymbol, usize>::new();
             for item in lr_group.item_list.iter() {
-                match item.get_next_symbol() {
-                    Some(symbol) => {
-                        let list = next_group_map.entry(symbol).or_insert_with(|| Vec::new());
-                        list.push(item);
-                    }
+                let symbol = match item.get_next_symbol(&self.single_pattern_rules) {
+                    Some(symbol) => symbol,
                     _ => continue,
+                };
+                let next_item = item.create_next();
+                match next_symbol_index_map.get(symbol) {
+                    Some(index) => next_group_map[*index].1.push(next_item),
+                    _ => {
+                        next_symbol_index_map.insert(symbol, next_group_map.len());
+                        next_group_map.push((symbol.clone(), vec![next_item]));
+                    }
                 }
             }
 
             let mut next_group_number_map = HashMap::<BNFSymbol, usize>::new();
-            let mut add_group_map = HashMap::<usize, LRGroup>::new();
-
-            for entry in next_group_map.iter() {
-                let symbol = entry.0;
-                let items = entry.1;
 
-                let mut next_group_items = Vec::<LRItem>::new();
-                for item in items.iter() {
-                    next_group_items.push(item.create_next().unwrap());
-                }
-
-                let mut group_number = Option::<usize>::None;
-                for entry in lr_group_map.iter() {
-                    let number = entry.0;
-                    let lr_group = entry.1;
-
-                    let mut is_all_matched = true;
-
-                    if next_group_items.len() == lr_group.default_item_list.len() {
-                        for i in 0..next_group_items.len() {
-                            let item = &next_group_items[i];
-                            let mut found = false;
+            for (symbol, mut kernel) in next_group_map {
+                kernel.sort_by_key(|item| (item.pattern_id, item.current_position));
+                let kernel_core = get_kernel_core(&kernel);
 
-                            for j in 0..lr_group.default_item_list.len() {
-                                let group_item = &lr_group.default_item_list[j];
-
-                                if item.root_name == group_item.root_name
-                                    && item.current_position == group_item.current_position
-                                    && item.pattern == group_item.pattern
-                                    && item.first_set == group_item.first_set
-                                {
-                                    found = true;
-                                    break;
-                                }
-                            }
+                let mut group_number = kernel_map.get(&kernel).cloned();
+                if group_number.is_none() && self.settings.table_mode != TableMode::Canonical {
+                    for number in core_map.get(&kernel_core).cloned().unwrap_or_default() {
+                        let lr_group = lr_group_map.get_mut(&number).unwrap();
+                        if !self.is_mergeable(lr_group, &kernel) {
+                            continue;
+                        }
 
-                            if !found {
-                                is_all_matched = false;
-                                break;
+                        // lookaheads which are added by merging must be propagated again
+                        let mut is_changed = false;
+                        for (group_item, item) in
+                            lr_group.default_item_list.iter_mut().zip(kernel.iter())
+                        {
+                            is_changed |= group_item.first_set.union_with(&item.first_set);
+                        }
+                        if is_changed {
+                            lr_group.item_list = lr_group.default_item_list.clone();
+                            self.add_items(lr_group);
+                            if !not_scanned_group_list.contains(&number) {
+                                not_scanned_group_list.push(number);
                             }
                         }
-                    } else {
-                        is_all_matched = false;
-                    }
 
-                    if is_all_matched {
-                        group_number = Some(*number);
+                        group_number = Some(number);
+                        break;
                     }
                 }
 
                 match group_number {
                     Some(number) => {
-                        next_group_number_map.insert(symbol.clone(), number);
+                        next_group_number_map.insert(symbol, number);
                     }
                     _ => {
                         let mut new_group = LRGroup::new(last_group_number);
-                        new_group.default_item_list = next_group_items.clone();
-                        new_group.item_list = next_group_items;
+                        new_group.default_item_list = kernel.clone();
+                        new_group.item_list = kernel.clone();
 
                         self.add_items(&mut new_group);
 
-                        add_group_map.insert(last_group_number, new_group);
+                        core_map
+                            .entry(kernel_core)
+                            .or_default()
+                            .push(last_group_number);
+                        if self.settings.table_mode == TableMode::Canonical {
+                            kernel_map.insert(kernel, last_group_number);
+                        }
+                        lr_group_map.insert(last_group_number, new_group);
                         not_scanned_group_list.push(last_group_number);
 
-                        next_group_number_map.insert(symbol.clone(), last_group_number);
+                        next_group_number_map.insert(symbol, last_group_number);
 
                         last_group_number += 1;
                     }
@@ -790,16 +1826,25 @@ impl ParserGenerator {
 
             let lr_group = lr_group_map.get_mut(&lr_group_number).unwrap();
             lr_group.next_group_number_map = next_group_number_map;
+        }
 
-            lr_group_map.extend(add_group_map);
+        // groups whose transitions are replaced by merging may be unreachable
+        if self.settings.table_mode != TableMode::Canonical {
+            lr_group_map = remove_unreachable_groups(lr_group_map, self.entries.len());
         }
 
         let mut table = Vec::<Vec<Option<Operation>>>::new();
+        let mut conflicts = Vec::<Conflict>::new();
         for group_number in 0..lr_group_map.len() {
             let group = lr_group_map.get(&group_number).unwrap();
             let mut operation_map = HashMap::<BNFSymbol, Operation>::new();
+            // the first reduction for each symbol, even if precedence resolves it
+            let mut reduction_map = HashMap::<BNFSymbol, usize>::new();
 
-            for entry in group.next_group_number_map.iter() {
+            let mut transitions = group.next_group_number_map.iter().collect::<Vec<_>>();
+            transitions.sort_by_key(|(_, next_group_number)| **next_group_number);
+
+            for entry in transitions {
                 let symbol = entry.0;
                 let next_group_number = *entry.1;
 
@@ -807,53 +1852,84 @@ impl ParserGenerator {
                     BNFSymbol::NonTerminalSymbolName(_) => {
                         self.insert_opreration(
                             &mut operation_map,
+                            &mut reduction_map,
                             symbol,
                             Operation::GoTo(next_group_number),
-                        )?;
+                            group_number,
+                            &mut conflicts,
+                        );
                     }
                     BNFSymbol::TerminalSymbolString(_) => {
                         self.insert_opreration(
                             &mut operation_map,
+                            &mut reduction_map,
                             symbol,
                             Operation::Shift(next_group_number),
-                        )?;
+                            group_number,
+                            &mut conflicts,
+                        );
                     }
                     BNFSymbol::TerminalSymbolRegex(_) => {
                         self.insert_opreration(
                             &mut operation_map,
+                            &mut reduction_map,
                             symbol,
                             Operation::Shift(next_group_number),
-                        )?;
+                            group_number,
+                            &mut conflicts,
+                        );
                     }
                     BNFSymbol::TerminalSymbolFunction(_) => {
                         self.insert_opreration(
                             &mut operation_map,
+                            &mut reduction_map,
                             symbol,
                             Operation::Shift(next_group_number),
-                        )?;
+                            group_number,
+                            &mut conflicts,
+                        );
+                    }
+                    BNFSymbol::TerminalSymbolLayout(_) | BNFSymbol::TerminalSymbolError => {
+                        self.insert_opreration(
+                            &mut operation_map,
+                            &mut reduction_map,
+                            symbol,
+                            Operation::Shift(next_group_number),
+                            group_number,
+                            &mut conflicts,
+                        );
                     }
                     _ => {
-                        return Err(format!("Unexpected symbol. {:?}", symbol));
+                        return Err(Error::new(
+                            Span::call_site(),
+                            format!("Internal error. Unexpected symbol. {:?}", symbol),
+                        ));
                     }
                 }
             }
 
             for item in group.item_list.iter() {
-                if item.is_last_position() {
-                    if item.root_name == " source" {
+                if item.is_last_position(&self.single_pattern_rules) {
+                    let rule = &self.single_pattern_rules[item.pattern_id];
+                    if self.is_start_rule(&rule.root_symbol_name) {
                         self.insert_opreration(
                             &mut operation_map,
+                            &mut reduction_map,
                             &BNFSymbol::EOF,
                             Operation::Accept,
-                        )?;
+                            group_number,
+                            &mut conflicts,
+                        );
                     } else {
-                        let pattern_id = self.get_pattern_id(item)?;
-                        for symbol in item.first_set.iter() {
+                        for symbol_id in item.first_set.iter() {
                             self.insert_opreration(
                                 &mut operation_map,
-                                symbol,
-                                Operation::Reduce(pattern_id),
-                            )?;
+                                &mut reduction_map,
+                                &self.symbol_list[symbol_id],
+                                Operation::Reduce(item.pattern_id),
+                                group_number,
+                                &mut conflicts,
+                            );
                         }
                     }
                 }
@@ -873,7 +1949,11 @@ impl ParserGenerator {
             table.push(operations);
         }
 
-        if !generate_code {
+        if !conflicts.is_empty() {
+            return Err(self.create_conflicts_report(&lr_group_map, &conflicts));
+        }
+
+        if !self.settings.generate_code {
             return Ok(String::new());
         }
 
@@ -901,38 +1981,105 @@ impl ParserGenerator {
         code += "
         use bnf_rules::bnf_rules_parser::lexer::{*};
         use bnf_rules::bnf_rules_parser::parser::{*};
+        use bnf_rules::bnf_rules_parser::table::{*};
         use bnf_rules::bnf_rules_parser::parser::ASTNode::{NonTerminal, Terminal};
+        use bnf_rules::bnf_rules_parser::parser::TerminalNode as __TerminalNode;
         ";
 
-        code += "pub fn parse_source(source: &str) -> Result<ASTNode, ParseError> {";
-
-        let mut array_str = String::new();
-        for rule_root_name in self.single_pattern_rules.iter() {
-            array_str += format!("\"{}\", ", &rule_root_name.root_symbol_name).as_str();
+        for (start_state, entry) in self.entries.iter().enumerate() {
+            code += format!(
+                "pub fn parse_{}(source: &str) -> {}<ASTNode, ParseError> {{ return __bnf_rules_parse(source, {}); }}",
+                entry, RESULT_PATH, start_state
+            )
+            .as_str();
+            code += format!(
+                "pub fn parse_{}_with_errors(source: &str) -> ({}<ASTNode>, {}<ParseError>) {{ return __bnf_rules_parse_with_errors(source, {}); }}",
+                entry, OPTION_PATH, VEC_PATH, start_state
+            )
+            .as_str();
         }
-        code += format!("static RULE_PATTERN_NAME: &[&str] = &[{}];", array_str).as_str();
-
-        let mut group_array_str = String::new();
-        for group in table.iter() {
-            let mut array_str = String::new();
 
-            for operation in group.iter() {
-                match operation {
-                    Some(operation) => {
-                        let tuple = operation.to_tuple();
-                        array_str += format!("({}, {}), ", tuple.0, tuple.1).as_str();
-                    }
-                    _ => array_str += format!("(0, 0), ").as_str(),
-                }
+        code += format!(
+            "fn __bnf_rules_parse(source: &str, start_state: usize) -> {}<ASTNode, ParseError> {{",
+            RESULT_PATH
+        )
+        .as_str();
+        code += "let tables = __bnf_rules_tables();";
+        code += match self.settings.scan_mode {
+            ScanMode::Batch => {
+                "let tokens = __bnf_rules_lexer().scan(source);
+                return __parse(tokens, tables, start_state);"
             }
+            ScanMode::OnDemand => {
+                "let scanner = __bnf_rules_lexer().scan_on_demand(source);
+                return __parse_on_demand(scanner, tables, start_state);"
+            }
+        };
+        code += "}";
 
-            group_array_str += format!("&[{}], ", array_str).as_str();
-        }
+        // errors of the lexer and the parser do not stop parsing
         code += format!(
-            "static LR_TABLE: &[&[(usize, usize)]] = &[{}];",
-            group_array_str
+            "fn __bnf_rules_parse_with_errors(source: &str, start_state: usize) -> ({}<ASTNode>, {}<ParseError>) {{",
+            OPTION_PATH, VEC_PATH
         )
         .as_str();
+        code += "let tables = __bnf_rules_tables();";
+        let mut array_str = String::new();
+        for (symbol, _) in self.settings.sync_symbols.iter() {
+            array_str += format!("{}, ", self.symbol_id_map[symbol]).as_str();
+        }
+        code += format!("let sync_symbols: &[u32] = &[{}];", array_str).as_str();
+        code += match self.symbol_id_map.get(&BNFSymbol::TerminalSymbolError) {
+            Some(symbol_id) => format!("let error_symbol = {}::Some({});", OPTION_PATH, symbol_id),
+            _ => format!("let error_symbol = {}::None;", OPTION_PATH),
+        }
+        .as_str();
+        code += match self.settings.scan_mode {
+            ScanMode::Batch => {
+                "let tokens = __bnf_rules_lexer().scan_with_recovery(source);
+                return __parse_with_errors(tokens, tables, sync_symbols, error_symbol, start_state);"
+            }
+            ScanMode::OnDemand => {
+                "let scanner = __bnf_rules_lexer().scan_on_demand(source);
+                return __parse_on_demand_with_errors(scanner, tables, sync_symbols, error_symbol, start_state);"
+            }
+        };
+        code += "}";
+
+        if self.settings.typed_ast {
+            for (start_state, entry) in self.entries.iter().enumerate() {
+                code += self.typed_entry_code(entry, start_state).as_str();
+            }
+            code += self.typed_ast_code()?.as_str();
+        }
+        if self.has_actions() {
+            for (start_state, entry) in self.entries.iter().enumerate() {
+                code += self.action_entry_code(entry, start_state).as_str();
+            }
+            code += self.action_code()?.as_str();
+        }
+        if self.settings.typed_ast || self.has_actions() {
+            code += self.parse_values_code().as_str();
+        }
+
+        code += "fn __bnf_rules_tables() -> &'static ParserTables {";
+
+        let mut array_str = String::new();
+        for rule_root_name in self.single_pattern_rules.iter() {
+            array_str += format!("\"{}\", ", &rule_root_name.root_symbol_name).as_str();
+        }
+        code += format!("static RULE_PATTERN_NAME: &[&str] = &[{}];", array_str).as_str();
+
+        let table_code = if self.settings.compress_table {
+            compressed_table_code(
+                &table,
+                &symbol_is_terminal,
+                self.settings.default_reductions,
+            )
+        } else {
+            dense_table_code(&table)
+        };
+        code += format!("static LR_TABLE: ParseTable = {};", table_code).as_str();
 
         let mut rule_array_str = String::new();
         for pattern in self.single_pattern_rules.iter() {
@@ -953,206 +2100,467 @@ impl ParserGenerator {
         )
         .as_str();
 
-        code += "let terminal_symbols = vec![";
-        for entry in self.symbol_id_map.iter() {
-            let symbol = entry.0;
-            let symbol_id = entry.1;
-
-            match symbol {
-                BNFSymbol::TerminalSymbolString(string) => {
-                    code += format!(
-                        "TerminalSymbol::new_from_string(r##########\"{}\"##########, {}),",
-                        string, symbol_id
+        // labels of symbols in each pattern, "" for symbols without labels
+        let mut rule_array_str = String::new();
+        for labels in self.get_pattern_labels() {
+            let mut array_str = String::new();
+            for label in labels.iter() {
+                array_str += format!("{:?}, ", label.clone().unwrap_or_default()).as_str();
+            }
+            rule_array_str += format!("&[{}], ", array_str).as_str();
+        }
+        code += format!("static RULE_LABELS: &[&[&str]] = &[{}];", rule_array_str).as_str();
+
+        let mut terminal_symbols = self
+            .symbol_id_map
+            .iter()
+            .filter(|(symbol, _)| {
+                symbol.is_terminal_symbol()
+                    && !matches!(
+                        symbol,
+                        BNFSymbol::EOF
+                            | BNFSymbol::TerminalSymbolLayout(_)
+                            | BNFSymbol::TerminalSymbolError
                     )
-                    .as_str();
+            })
+            .collect::<Vec<_>>();
+        terminal_symbols.sort_by_key(|(symbol, _)| self.settings.get_lexer_order(symbol));
+
+        // names of terminal symbols in error messages, empty for non-terminal symbols
+        let mut array_str = String::new();
+        for symbol in self.symbol_list.iter() {
+            array_str += format!("{:?}, ", self.get_display_name(symbol)).as_str();
+        }
+        code += format!("static TERMINAL_NAMES: &[&str] = &[{}];", array_str).as_str();
+
+        code += "static TABLES: ParserTables = ParserTables {
+            rule_pattern_name: RULE_PATTERN_NAME,
+            lr_table: &LR_TABLE,
+            bnf_rules: BNF_RULES,
+            rule_labels: RULE_LABELS,
+            terminal_names: TERMINAL_NAMES,
+        };";
+        code += "return &TABLES;";
+        code += "}";
+
+        // regexes are compiled only once
+        code += "fn __bnf_rules_lexer() -> &'static Lexer {";
+        code += "static LEXER: std::sync::OnceLock<Lexer> = std::sync::OnceLock::new();";
+        code += "return LEXER.get_or_init(|| {";
+
+        // terminal symbols and the transition in each mode
+        let lexer_modes = &self.settings.lexer_modes;
+        let mut mode_symbols = vec![Vec::new(); lexer_modes.len()];
+        for (symbol, symbol_id) in terminal_symbols {
+            match self.settings.terminal_symbol_map.get(symbol) {
+                Some(settings) => {
+                    for (mode, transition) in settings.modes.iter() {
+                        let mode = lexer_modes.iter().position(|name| name == mode).unwrap();
+                        mode_symbols[mode].push((symbol, *symbol_id, transition.clone()));
+                    }
                 }
-                BNFSymbol::TerminalSymbolRegex(regex) => {
-                    code += format!(
-                        "TerminalSymbol::new_from_regex(r##########\"{}\"##########, {}),",
-                        regex, symbol_id
-                    )
-                    .as_str();
+                _ => mode_symbols[0].push((symbol, *symbol_id, None)),
+            }
+        }
+
+        code += "let modes = vec![";
+        for (mode, symbols) in mode_symbols.iter().enumerate() {
+            code += "LexerMode::new(vec![";
+            for (symbol, symbol_id, transition) in symbols.iter() {
+                code += terminal_symbol_code(symbol, *symbol_id).as_str();
+                code.pop(); // ','
+
+                if let Some(settings) = self.settings.terminal_symbol_map.get(*symbol) {
+                    if !settings.reserved.is_empty() {
+                        code += ".with_reserved(&[";
+                        for keyword in settings.reserved.iter() {
+                            code += format!("r##########\"{}\"##########, ", keyword).as_str();
+                        }
+                        code += "])";
+                    }
                 }
-                BNFSymbol::TerminalSymbolFunction(fn_string) => {
-                    code += format!(
-                        "TerminalSymbol::new_from_tokenizer_fn({}, {}),",
-                        fn_string, symbol_id
-                    )
-                    .as_str();
+                match transition {
+                    Some(ModeTransition::Push(pushed)) => {
+                        let pushed = lexer_modes.iter().position(|name| name == pushed).unwrap();
+                        code += format!(".with_push({})", pushed).as_str();
+                    }
+                    Some(ModeTransition::Pop) => code += ".with_pop()",
+                    _ => {}
+                }
+                code += ",";
+            }
+            code += "], vec![";
+
+            // skip symbols only belong to the default mode
+            if mode == 0 {
+                if self.settings.skip_default {
+                    code += "TerminalSymbol::new_from_regex(DEFAULT_SKIP_REGEX, SKIP_SYMBOL_ID),";
+                }
+                for symbol in self.settings.skip_symbols.iter() {
+                    code += terminal_symbol_code(symbol, SKIP_SYMBOL_ID as usize).as_str();
                 }
-                _ => {}
             }
+            code += "]),";
         }
         code += "];";
-        code += "let lexer = Lexer::new(terminal_symbols);";
-
-        code += "let tokens = lexer.scan(source);";
-        code += "return __parse(tokens, RULE_PATTERN_NAME, LR_TABLE, BNF_RULES);";
+        if self.settings.layout {
+            let layout_ids = LAYOUT_SYMBOLS
+                .map(|name| self.symbol_id_map[&BNFSymbol::TerminalSymbolLayout(name.to_string())]);
+            code += format!(
+                "return Lexer::new_with_modes(modes).with_layout({}, {}, {});",
+                layout_ids[0], layout_ids[1], layout_ids[2]
+            )
+            .as_str();
+        } else {
+            code += "return Lexer::new_with_modes(modes);";
+        }
+        code += "});";
         code += "}";
 
         return Ok(code);
     }
 
+    fn check_sync_symbols(&self) -> Result<(), Error> {
+        for (symbol, span) in self.settings.sync_symbols.iter() {
+            if !self.symbol_id_map.contains_key(symbol) {
+                return Err(Error::new(*span, "Sync symbol is not used in rules."));
+            }
+        }
+        return Ok(());
+    }
+
+    /// Name of the terminal symbol in error messages.<br>
+    /// Literal strings are quoted, regexes and functions use '#[display = "name"]' if it is specified.
+    fn get_display_name(&self, symbol: &BNFSymbol) -> String {
+        let display_name = self
+            .settings
+            .terminal_symbol_map
+            .get(symbol)
+            .and_then(|settings| settings.display_name.clone());
+        return match symbol {
+            // 'error' is never expected
+            BNFSymbol::NonTerminalSymbolName(_)
+            | BNFSymbol::TerminalSymbolError
+            | BNFSymbol::Null => String::new(),
+            BNFSymbol::TerminalSymbolString(text) => format!("{:?}", text),
+            BNFSymbol::TerminalSymbolRegex(regex) => {
+                display_name.unwrap_or_else(|| format!("r\"{}\"", regex))
+            }
+            BNFSymbol::TerminalSymbolFunction(name) => {
+                display_name.unwrap_or_else(|| format!("fn ({})", name))
+            }
+            BNFSymbol::TerminalSymbolLayout(name) => name.clone(),
+            BNFSymbol::EOF => "EOF".to_string(),
+        };
+    }
+
+    /// Reductions by different patterns always conflict,
+    /// even if precedence has resolved the first one with a shift.
     fn insert_opreration(
         &self,
         operation_map: &mut HashMap<BNFSymbol, Operation>,
+        reduction_map: &mut HashMap<BNFSymbol, usize>,
         symbol: &BNFSymbol,
         operation: Operation,
-    ) -> Result<(), String> {
-        if operation_map.contains_key(symbol) {
-            let mut message = format!(
-                "{:?} {:?} conflict!",
-                operation_map.get(symbol).unwrap(),
-                operation
-            );
-
-            match symbol {
-                BNFSymbol::NonTerminalSymbolName(symbol_name) => {
-                    if !symbol_name.is_empty() {
-                        message += format!(" | Symbol : {} ::=", symbol_name).as_str();
-
-                        let rule = self.rule_map.get(symbol_name).unwrap();
-                        for pattern in rule.or_patterns.iter() {
-                            for symbol in pattern.iter() {
-                                message += format!(" {} ", symbol.get_symbol_name()).as_str();
-                            }
-                            message += "|";
-                        }
-                    }
+        group_number: usize,
+        conflicts: &mut Vec<Conflict>,
+    ) {
+        if let Operation::Reduce(pattern_id) = operation {
+            match reduction_map.get(symbol) {
+                Some(reduced_pattern_id) if *reduced_pattern_id != pattern_id => {
+                    conflicts.push(Conflict {
+                        group_number,
+                        symbol: symbol.clone(),
+                        operations: (Operation::Reduce(*reduced_pattern_id), operation),
+                    });
+                    return;
                 }
+                Some(_) => {}
                 _ => {
-                    message += format!(" | Symbol : '{}'", symbol.get_symbol_name()).as_str();
+                    reduction_map.insert(symbol.clone(), pattern_id);
                 }
             }
+        }
 
-            return Err(message);
+        if let Some(current_operation) = operation_map.get(symbol) {
+            match self.resolve_conflict(symbol, current_operation, &operation) {
+                Some(resolved) => {
+                    operation_map.insert(symbol.clone(), resolved);
+                }
+                _ => {
+                    // Keep the first operation to continue collecting conflicts.
+                    conflicts.push(Conflict {
+                        group_number,
+                        symbol: symbol.clone(),
+                        operations: (current_operation.clone(), operation),
+                    });
+                }
+            }
+            return;
         }
         operation_map.insert(symbol.clone(), operation);
-        return Ok(());
     }
 
-    fn add_items(&self, lr_group: &mut LRGroup) {
-        loop {
-            let mut is_all_scanned = true;
-            let mut add_item_list = Vec::<LRItem>::new();
-
-            for i in 0..lr_group.item_list.len() {
-                let item = &mut lr_group.item_list[i];
-
-                if item.is_scanned || item.is_last_position() {
-                    continue;
-                }
-                is_all_scanned = false;
-                item.is_scanned = true;
-
-                let next_symbol_name = match item.get_next_nonterminal_symbol_name() {
-                    Some(name) => name,
-                    _ => continue,
+    /// Resolve a shift/reduce conflict by declared precedence and associativity.<br>
+    /// Returns None if the conflict cannot be resolved.
+    fn resolve_conflict(
+        &self,
+        symbol: &BNFSymbol,
+        current_operation: &Operation,
+        operation: &Operation,
+    ) -> Option<Operation> {
+        let (shift, pattern_id) = match (current_operation, operation) {
+            (Operation::Shift(_), Operation::Reduce(pattern_id))
+            | (Operation::Reduce(pattern_id), Operation::Shift(_)) => {
+                let shift = match current_operation {
+                    Operation::Shift(_) => current_operation,
+                    _ => operation,
                 };
+                (shift, *pattern_id)
+            }
+            _ => return None,
+        };
+
+        let symbol_precedence = self.settings.precedence_map.get(symbol)?;
+        let pattern_precedence = self.single_pattern_rules[pattern_id].precedence.as_ref()?;
 
-                let item = &lr_group.item_list[i];
+        return if pattern_precedence.level > symbol_precedence.level {
+            Some(Operation::Reduce(pattern_id))
+        } else if pattern_precedence.level < symbol_precedence.level {
+            Some(shift.clone())
+        } else {
+            match symbol_precedence.associativity {
+                Associativity::Left => Some(Operation::Reduce(pattern_id)),
+                Associativity::Right => Some(shift.clone()),
+                Associativity::NonAssoc => Some(Operation::Error),
+            }
+        };
+    }
 
-                let latter_pattern = item.get_latter_pattern();
+    /// Add items of the closure, merging lookaheads of the same items.
+    fn add_items(&self, lr_group: &mut LRGroup) {
+        let item_list = &mut lr_group.item_list;
 
-                let first_set = self.get_first_set(&latter_pattern, &item.first_set);
+        let mut item_index_map = HashMap::<(usize, usize), usize>::new();
+        for (index, item) in item_list.iter().enumerate() {
+            item_index_map.insert((item.pattern_id, item.current_position), index);
+        }
 
-                let rule = self
-                    .rule_map
-                    .get(&next_symbol_name)
-                    .unwrap_or_else(|| panic!("No symbol found : '{}'", &next_symbol_name));
+        let mut not_scanned_items = (0..item_list.len()).rev().collect::<Vec<_>>();
+        while let Some(index) = not_scanned_items.pop() {
+            let item = &item_list[index];
+            let next_pattern_ids = match item.get_next_symbol(&self.single_pattern_rules) {
+                Some(BNFSymbol::NonTerminalSymbolName(name)) => &self.rule_pattern_map[name],
+                _ => continue,
+            };
+            let first_set = self.get_child_first_set(item);
 
-                for pattern in rule.or_patterns.iter() {
-                    let mut found = false;
-                    for item in lr_group.item_list.iter_mut() {
-                        if item.root_name == next_symbol_name
-                            && &item.pattern == pattern
-                            && item.current_position == 0
-                        {
-                            found = true;
-                            item.first_set.extend(first_set.clone());
-                            break;
+            for pattern_id in next_pattern_ids.iter() {
+                match item_index_map.get(&(*pattern_id, 0)) {
+                    Some(child_index) => {
+                        if item_list[*child_index].first_set.union_with(&first_set) {
+                            not_scanned_items.push(*child_index);
                         }
                     }
+                    _ => {
+                        item_index_map.insert((*pattern_id, 0), item_list.len());
+                        not_scanned_items.push(item_list.len());
+                        item_list.push(LRItem::new(*pattern_id, 0, first_set.clone()));
+                    }
+                }
+            }
+        }
+    }
 
-                    if !found {
-                        let mut item = LRItem::new(next_symbol_name.clone());
+    /// Lookaheads of items which are added by the next non-terminal symbol of the item.
+    pub(crate) fn get_child_first_set(&self, item: &LRItem) -> SymbolSet {
+        let (latter_first_set, is_latter_nullable) =
+            &self.latter_first_sets[item.pattern_id][item.current_position];
 
-                        for symbol in pattern.iter() {
-                            match symbol {
-                                BNFSymbol::Null => {}
-                                BNFSymbol::EOF => {}
-                                _ => {
-                                    item.pattern.push(symbol.clone());
-                                }
-                            }
+        let mut first_set = latter_first_set.clone();
+        if *is_latter_nullable {
+            first_set.union_with(&item.first_set);
+        }
+        return first_set;
+    }
+
+    /// Whether the kernel can be merged into the group which has the same core.<br>
+    /// Canonical LR(1) merges only the same lookaheads, LALR(1) merges any lookaheads,
+    /// and Pager's method merges lookaheads which do not make new reduce/reduce conflicts.
+    fn is_mergeable(&self, lr_group: &LRGroup, kernel: &Vec<LRItem>) -> bool {
+        let lookaheads = lr_group
+            .default_item_list
+            .iter()
+            .zip(kernel.iter())
+            .map(|(group_item, item)| (&group_item.first_set, &item.first_set))
+            .collect::<Vec<_>>();
+
+        return match self.settings.table_mode {
+            TableMode::Canonical => lookaheads.iter().all(|(current, new)| current == new),
+            TableMode::LALR => true,
+            TableMode::Pager => {
+                let mut is_weakly_compatible = true;
+                for i in 0..lookaheads.len() {
+                    for j in (i + 1)..lookaheads.len() {
+                        let (current_i, new_i) = lookaheads[i];
+                        let (current_j, new_j) = lookaheads[j];
+
+                        if (current_i.is_disjoint(new_j) && new_i.is_disjoint(current_j))
+                            || !current_i.is_disjoint(current_j)
+                            || !new_i.is_disjoint(new_j)
+                        {
+                            continue;
                         }
-                        item.first_set = first_set.clone();
-                        add_item_list.push(item);
+                        is_weakly_compatible = false;
                     }
                 }
+                is_weakly_compatible
             }
+        };
+    }
 
-            lr_group.item_list.extend(add_item_list);
-
-            if is_all_scanned {
-                break;
+    /// Labels of symbols in each pattern of 'single_pattern_rules'.
+    fn get_pattern_labels(&self) -> Vec<Vec<Option<String>>> {
+        let mut pattern_labels = Vec::<Vec<Option<String>>>::new();
+        for (pattern_id, pattern_rule) in self.single_pattern_rules.iter().enumerate() {
+            let rule = &self.rule_map[&pattern_rule.root_symbol_name];
+            let index = self.rule_pattern_map[&pattern_rule.root_symbol_name]
+                .iter()
+                .position(|id| *id == pattern_id)
+                .unwrap();
+
+            let mut labels = Vec::<Option<String>>::new();
+            for (symbol, label) in rule.or_patterns[index]
+                .iter()
+                .zip(rule.pattern_infos[index].labels.iter())
+            {
+                if !matches!(symbol, BNFSymbol::Null | BNFSymbol::EOF) {
+                    labels.push(label.clone());
+                }
             }
+            pattern_labels.push(labels);
         }
+        return pattern_labels;
     }
 
-    fn get_pattern_id(&self, item: &LRItem) -> Result<usize, String> {
-        for i in 0..self.single_pattern_rules.len() {
-            let pattern = &self.single_pattern_rules[i];
-            if pattern.root_symbol_name == item.root_name && pattern.pattern == item.pattern {
-                return Ok(i);
-            }
-        }
-        return Err(format!("Internal error. Not found pattern. {:?}", item));
+    fn is_start_rule(&self, name: &str) -> bool {
+        return match self.rule_map.get(name) {
+            Some(rule) => rule.kind == BNFRuleKind::Start,
+            _ => false,
+        };
     }
 
-    fn get_symbol_id(&self, symbol: &BNFSymbol) -> Result<usize, String> {
+    fn get_symbol_id(&self, symbol: &BNFSymbol) -> Result<usize, Error> {
         return match self.symbol_id_map.get(symbol) {
             Some(id) => Ok(*id),
-            _ => Err(format!(
-                "Internal error. Symbol's id is not found. {:?}",
-                symbol
+            _ => Err(Error::new(
+                Span::call_site(),
+                format!("Internal error. Symbol's id is not found. {:?}", symbol),
             )),
         };
     }
 
-    fn get_first_set(
-        &self,
-        symbol_list: &Vec<BNFSymbol>,
-        item_first_set: &HashSet<BNFSymbol>,
-    ) -> HashSet<BNFSymbol> {
-        let mut first_set = HashSet::<BNFSymbol>::new();
+    /// Compute first sets of the rest of patterns after each position.
+    fn search_latter_first_sets(&mut self) {
+        let mut latter_first_sets = Vec::<Vec<(SymbolSet, bool)>>::new();
 
-        let mut is_nullable = true;
-        for symbol in symbol_list.iter() {
-            match symbol {
-                BNFSymbol::NonTerminalSymbolName(name) => {
-                    let rule = self.rule_map.get(name).unwrap();
-                    first_set.extend(rule.first_set.clone());
-                    if !rule.is_nullable {
-                        is_nullable = false;
-                        break;
+        for rule in self.single_pattern_rules.iter() {
+            let mut first_sets = Vec::<(SymbolSet, bool)>::new();
+
+            for position in 0..rule.pattern.len() {
+                let mut first_set = SymbolSet::new(self.symbol_list.len());
+                let mut is_nullable = true;
+
+                for symbol in rule.pattern[(position + 1)..].iter() {
+                    match symbol {
+                        BNFSymbol::NonTerminalSymbolName(name) => {
+                            let rule = &self.rule_map[name];
+                            for symbol in rule.first_set.iter() {
+                                first_set.insert(self.symbol_id_map[symbol]);
+                            }
+                            if !rule.is_nullable {
+                                is_nullable = false;
+                                break;
+                            }
+                        }
+                        _ => {
+                            first_set.insert(self.symbol_id_map[symbol]);
+                            is_nullable = false;
+                            break;
+                        }
                     }
                 }
-                BNFSymbol::Null => {}
-                _ => {
-                    first_set.insert(symbol.clone());
-                    is_nullable = false;
-                    break;
-                }
+
+                first_sets.push((first_set, is_nullable));
             }
+
+            latter_first_sets.push(first_sets);
         }
 
-        if is_nullable {
-            first_set.extend(item_first_set.clone());
+        self.latter_first_sets = latter_first_sets;
+    }
+}
+
+/// Cores of kernel items, which identify LR(0) states.
+fn get_kernel_core(kernel: &Vec<LRItem>) -> Vec<(usize, usize)> {
+    return kernel
+        .iter()
+        .map(|item| (item.pattern_id, item.current_position))
+        .collect();
+}
+
+/// Code to construct 'TerminalSymbol' for the lexer.
+fn terminal_symbol_code(symbol: &BNFSymbol, symbol_id: usize) -> String {
+    return match symbol {
+        BNFSymbol::TerminalSymbolString(string) => format!(
+            "TerminalSymbol::new_from_string(r##########\"{}\"##########, {}),",
+            string, symbol_id
+        ),
+        BNFSymbol::TerminalSymbolRegex(regex) => format!(
+            "TerminalSymbol::new_from_regex(r##########\"{}\"##########, {}),",
+            regex, symbol_id
+        ),
+        BNFSymbol::TerminalSymbolFunction(fn_string) => format!(
+            "TerminalSymbol::new_from_tokenizer_fn({}, {}),",
+            fn_string, symbol_id
+        ),
+        _ => String::new(),
+    };
+}
+
+/// Renumber groups which are reachable from start groups, keeping the order.
+fn remove_unreachable_groups(
+    mut lr_group_map: HashMap<usize, LRGroup>,
+    start_groups: usize,
+) -> HashMap<usize, LRGroup> {
+    let mut reachable_groups = HashSet::<usize>::new();
+    let mut not_scanned_groups = (0..start_groups).collect::<Vec<_>>();
+    while let Some(number) = not_scanned_groups.pop() {
+        if !reachable_groups.insert(number) {
+            continue;
         }
+        not_scanned_groups.extend(lr_group_map[&number].next_group_number_map.values());
+    }
 
-        return first_set;
+    let mut numbers = reachable_groups.into_iter().collect::<Vec<_>>();
+    numbers.sort();
+    let number_map = numbers
+        .iter()
+        .enumerate()
+        .map(|(new_number, number)| (*number, new_number))
+        .collect::<HashMap<_, _>>();
+
+    let mut new_group_map = HashMap::<usize, LRGroup>::new();
+    for number in numbers {
+        let mut group = lr_group_map.remove(&number).unwrap();
+        group.group_number = number_map[&number];
+        for next_number in group.next_group_number_map.values_mut() {
+            *next_number = number_map[next_number];
+        }
+        new_group_map.insert(group.group_number, group);
     }
+
+    return new_group_map;
 }
 
 #[derive(Debug)]
@@ -1174,79 +2582,56 @@ impl LRGroup {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub struct LRItem {
-    pub root_name: String,
-    pub pattern: Vec<BNFSymbol>,
-    pub first_set: HashSet<BNFSymbol>,
+    /// The index of 'single_pattern_rules'.
+    pub pattern_id: usize,
     pub current_position: usize,
-    pub is_scanned: bool,
+    /// Ids of lookahead symbols.
+    pub first_set: SymbolSet,
 }
 
 impl LRItem {
-    pub fn new(root_name: String) -> Self {
+    pub fn new(pattern_id: usize, current_position: usize, first_set: SymbolSet) -> Self {
         return Self {
-            root_name,
-            pattern: Vec::new(),
-            first_set: HashSet::new(),
-            current_position: 0,
-            is_scanned: false,
-        };
-    }
-
-    pub fn is_last_position(&self) -> bool {
-        return self.pattern.len() == self.current_position;
-    }
-
-    pub fn get_next_nonterminal_symbol_name(&self) -> Option<String> {
-        return match self.pattern.get(self.current_position) {
-            Some(symbol) => match symbol {
-                BNFSymbol::NonTerminalSymbolName(name) => Some(name.clone()),
-                _ => None,
-            },
-            _ => None,
+            pattern_id,
+            current_position,
+            first_set,
         };
     }
 
-    pub fn get_next_symbol(&self) -> Option<BNFSymbol> {
-        return self.pattern.get(self.current_position).cloned();
+    pub fn is_last_position(&self, rules: &Vec<SinglePatternRule>) -> bool {
+        return rules[self.pattern_id].pattern.len() == self.current_position;
     }
 
-    pub fn get_latter_pattern(&self) -> Vec<BNFSymbol> {
-        let mut latter_pattern = Vec::<BNFSymbol>::new();
-        if self.current_position + 1 >= self.pattern.len() {
-            return latter_pattern;
-        }
-
-        for i in (self.current_position + 1)..self.pattern.len() {
-            let symbol = &self.pattern[i];
-            latter_pattern.push(symbol.clone());
-        }
-
-        return latter_pattern;
+    pub fn get_next_symbol<'a>(&self, rules: &'a Vec<SinglePatternRule>) -> Option<&'a BNFSymbol> {
+        return rules[self.pattern_id].pattern.get(self.current_position);
     }
 
-    pub fn create_next(&self) -> Option<Self> {
-        if self.is_last_position() {
-            return None;
-        }
-
-        let mut cloned = self.clone();
-        cloned.current_position += 1;
-        cloned.is_scanned = false;
-
-        return Some(cloned);
+    pub fn create_next(&self) -> Self {
+        return Self::new(
+            self.pattern_id,
+            self.current_position + 1,
+            self.first_set.clone(),
+        );
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct SinglePatternRule {
     pub root_symbol_name: String,
     pub pattern: Vec<BNFSymbol>,
+    pub precedence: Option<Precedence>,
+    pub span: Span,
 }
 
 impl SinglePatternRule {
-    pub fn new(root_symbol_name: String, pattern: Vec<BNFSymbol>) -> Self {
+    pub fn new(
+        root_symbol_name: String,
+        pattern: Vec<BNFSymbol>,
+        precedence: Option<Precedence>,
+        span: Span,
+    ) -> Self {
         let mut new_pattern = Vec::<BNFSymbol>::new();
         for symbol in pattern.iter() {
             match symbol {
@@ -1260,6 +2645,8 @@ impl SinglePatternRule {
         return Self {
             root_symbol_name,
             pattern: new_pattern,
+            precedence,
+            span,
         };
     }
 }
@@ -1270,6 +2657,8 @@ pub enum Operation {
     Reduce(usize),
     GoTo(usize),
     Accept,
+    /// An explicit error which is made by '#[nonassoc]'.
+    Error,
 }
 
 pub const OPERATION_NONE: usize = 0;
@@ -1285,6 +2674,7 @@ impl Operation {
             Operation::Reduce(i) => (OPERATION_REDUCE, *i),
             Operation::GoTo(i) => (OPERATION_GOTO, *i),
             Operation::Accept => (OPERATION_ACCEPT, 0),
+            Operation::Error => (OPERATION_NONE, 0),
         };
     }
 }
@@ -0,0 +1,124 @@
+use crate::lexer::TokenPosition;
+
+/// Start offsets of lines in a source, which converts byte offsets to lines and columns and back.<br>
+/// Lines and columns start from 1. Line breaks are LF, CR and CRLF.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'input> {
+    source: &'input str,
+    /// Byte offsets of the start of each line.
+    line_starts: Vec<usize>,
+    /// Whether each line is ASCII only, then all column units are the same.
+    ascii_lines: Vec<bool>,
+}
+
+/// Unit of columns, e.g. 'Utf16' for the language server protocol.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ColumnUnit {
+    Utf8,
+    Char,
+    Utf16,
+}
+
+impl<'input> LineIndex<'input> {
+    pub fn new(source: &'input str) -> Self {
+        let bytes = source.as_bytes();
+        let mut line_starts = vec![0];
+        let mut ascii_lines = Vec::new();
+        let mut is_ascii = true;
+
+        let mut index = 0;
+        while index < bytes.len() {
+            let line_break_length = match bytes[index] {
+                b'\r' if bytes.get(index + 1) == Some(&b'\n') => 2,
+                b'\r' | b'\n' => 1,
+                byte => {
+                    is_ascii &= byte.is_ascii();
+                    index += 1;
+                    continue;
+                }
+            };
+            index += line_break_length;
+
+            line_starts.push(index);
+            ascii_lines.push(is_ascii);
+            is_ascii = true;
+        }
+        ascii_lines.push(is_ascii);
+
+        return Self {
+            source,
+            line_starts,
+            ascii_lines,
+        };
+    }
+
+    pub fn line_count(&self) -> usize {
+        return self.line_starts.len();
+    }
+
+    /// Line and column of the byte offset.<br>
+    /// If the offset is inside a char, it is the column of the char.
+    pub fn line_column(&self, byte_offset: usize, unit: ColumnUnit) -> (usize, usize) {
+        let mut byte_offset = byte_offset.min(self.source.len());
+        while !self.source.is_char_boundary(byte_offset) {
+            byte_offset -= 1;
+        }
+
+        let line = self
+            .line_starts
+            .partition_point(|line_start| *line_start <= byte_offset);
+        let line_start = self.line_starts[line - 1];
+
+        let column = if self.ascii_lines[line - 1] {
+            byte_offset - line_start
+        } else {
+            column_length(&self.source[line_start..byte_offset], unit)
+        };
+
+        return (line, column + 1);
+    }
+
+    /// Byte offset of the line and column, or None if it is out of the source or inside a char.
+    pub fn byte_offset(&self, line: usize, column: usize, unit: ColumnUnit) -> Option<usize> {
+        let line_start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let line_end = match self.line_starts.get(line) {
+            Some(next_line_start) => *next_line_start,
+            _ => self.source.len(),
+        };
+        let target_length = column.checked_sub(1)?;
+
+        let mut length = 0;
+        for (index, char) in self.source[line_start..line_end].char_indices() {
+            if length == target_length {
+                return Some(line_start + index);
+            }
+            length += column_length(&self.source[line_start + index..][..char.len_utf8()], unit);
+        }
+
+        // the end of the line
+        return if length == target_length {
+            Some(line_end)
+        } else {
+            None
+        };
+    }
+
+    /// Fills the lines and columns of the start and the end of the position, in chars.
+    pub fn set_line_and_column(&self, position: &mut TokenPosition) {
+        let (line, column) = self.line_column(position.start_position, ColumnUnit::Char);
+        position.line = line;
+        position.column = column;
+
+        let (end_line, end_column) = self.line_column(position.range().end, ColumnUnit::Char);
+        position.end_line = end_line;
+        position.end_column = end_column;
+    }
+}
+
+fn column_length(text: &str, unit: ColumnUnit) -> usize {
+    return match unit {
+        ColumnUnit::Utf8 => text.len(),
+        ColumnUnit::Char => text.chars().count(),
+        ColumnUnit::Utf16 => text.encode_utf16().count(),
+    };
+}
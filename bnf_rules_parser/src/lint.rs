@@ -0,0 +1,180 @@
+use crate::{combine_errors, sorted_rules, BNFRule, BNFRuleKind, BNFSymbol};
+use proc_macro2::{Span, TokenStream};
+use quote::quote_spanned;
+use std::collections::{HashMap, HashSet};
+use syn::Error;
+
+/// A problem in the grammar which does not abort the compilation.
+#[derive(Debug, Clone)]
+pub struct LintWarning {
+    pub span: Span,
+    pub message: String,
+}
+
+impl LintWarning {
+    pub fn new(span: Span, message: String) -> Self {
+        return Self { span, message };
+    }
+
+    pub fn to_compile_warning(&self) -> TokenStream {
+        return compile_warning(self.span, &self.message);
+    }
+}
+
+/// Code which makes the compiler show a warning at the span, used for all warnings of 'bnf_rules!'.<br>
+/// Stable proc-macros cannot emit warnings, so this uses an item marked '#[deprecated]',
+/// and the compiler shows "use of deprecated unit struct 'bnf_rules_warning': {message}".<br>
+/// The item is in an anonymous const, so it never conflicts with the generated code.
+pub fn compile_warning(span: Span, message: &str) -> TokenStream {
+    return quote_spanned! {span=>
+        const _: () = {
+            #[deprecated(note = #message)]
+            #[allow(non_camel_case_types)]
+            struct bnf_rules_warning;
+            let _ = bnf_rules_warning;
+        };
+    };
+}
+
+/// Check undefined, unreachable, unproductive rules and useless '[ ]', '{ }'.<br>
+/// Rules which have problems making the parser broken are reported as errors.
+pub fn check_rules(rule_map: &HashMap<String, BNFRule>) -> Result<Vec<LintWarning>, Error> {
+    let mut errors = Vec::<Error>::new();
+    let mut warnings = Vec::<LintWarning>::new();
+
+    // undefined rules
+    for rule in sorted_rules(rule_map) {
+        for (pattern, pattern_info) in rule.or_patterns.iter().zip(rule.pattern_infos.iter()) {
+            for (symbol, span) in pattern.iter().zip(pattern_info.symbol_spans.iter()) {
+                if let BNFSymbol::NonTerminalSymbolName(name) = symbol {
+                    if !rule_map.contains_key(name) {
+                        errors.push(Error::new(*span, format!("No symbol found : '{}'", name)));
+                    }
+                }
+            }
+        }
+    }
+    if let Some(error) = combine_errors(errors) {
+        return Err(error);
+    }
+    let mut errors = Vec::<Error>::new();
+
+    // unproductive rules
+    let productive_rules = search_rules(rule_map, |symbol, rule_set| match symbol {
+        BNFSymbol::NonTerminalSymbolName(name) => rule_set.contains(name),
+        _ => true,
+    });
+    for rule in sorted_rules(rule_map) {
+        if rule.kind == BNFRuleKind::Named
+            && !productive_rules.contains(&rule.non_terminal_symbol_name)
+        {
+            errors.push(Error::new(
+                rule.span,
+                format!(
+                    "Rule '{}' never terminates, it is infinitely recursive.",
+                    rule.non_terminal_symbol_name
+                ),
+            ));
+        }
+    }
+
+    // unreachable rules
+    let mut reachable_rules = HashSet::<&String>::new();
+    let mut not_scanned_rules = Vec::<&String>::new();
+    let mut entries = Vec::<String>::new();
+    for rule in sorted_rules(rule_map) {
+        if rule.kind == BNFRuleKind::Start {
+            reachable_rules.insert(&rule.non_terminal_symbol_name);
+            not_scanned_rules.push(&rule.non_terminal_symbol_name);
+            entries.push(format!("'{}'", rule.non_terminal_symbol_name.trim_start()));
+        }
+    }
+    let entries = entries.join(", ");
+    while let Some(name) = not_scanned_rules.pop() {
+        for pattern in rule_map[name].or_patterns.iter() {
+            for symbol in pattern.iter() {
+                if let BNFSymbol::NonTerminalSymbolName(name) = symbol {
+                    if reachable_rules.insert(name) {
+                        not_scanned_rules.push(name);
+                    }
+                }
+            }
+        }
+    }
+    for rule in sorted_rules(rule_map) {
+        if rule.kind == BNFRuleKind::Named
+            && !reachable_rules.contains(&rule.non_terminal_symbol_name)
+        {
+            warnings.push(LintWarning::new(
+                rule.span,
+                format!(
+                    "Rule '{}' is unreachable from {}.",
+                    rule.non_terminal_symbol_name, entries
+                ),
+            ));
+        }
+    }
+
+    // '[ ]' and '{ }' around nullable contents
+    let nullable_rules = search_rules(rule_map, |symbol, rule_set| match symbol {
+        BNFSymbol::NonTerminalSymbolName(name) => rule_set.contains(name),
+        BNFSymbol::Null => true,
+        _ => false,
+    });
+    for rule in sorted_rules(rule_map) {
+        let content = match rule.kind {
+            BNFRuleKind::Optional => &rule.or_patterns[0][0],
+            BNFRuleKind::Repetition => &rule.or_patterns[1][0],
+            _ => continue,
+        };
+        if !nullable_rules.contains(content.get_symbol_name()) {
+            continue;
+        }
+
+        let message = match rule.kind {
+            BNFRuleKind::Optional => "'[ ]' is useless, because the content can be empty.",
+            _ => "'{ }' around the content which can be empty makes the grammar ambiguous.",
+        };
+        warnings.push(LintWarning::new(rule.span, message.to_string()));
+    }
+
+    return match combine_errors(errors) {
+        Some(error) => Err(error),
+        _ => Ok(warnings),
+    };
+}
+
+/// Collect rules which have a pattern whose symbols are all accepted by 'is_accepted'.
+fn search_rules<F>(rule_map: &HashMap<String, BNFRule>, is_accepted: F) -> HashSet<String>
+where
+    F: Fn(&BNFSymbol, &HashSet<String>) -> bool,
+{
+    let mut rule_set = HashSet::<String>::new();
+
+    loop {
+        let mut retry = false;
+
+        for rule in rule_map.values() {
+            if rule_set.contains(&rule.non_terminal_symbol_name) {
+                continue;
+            }
+
+            let is_accepted_rule = rule.or_patterns.is_empty()
+                || rule
+                    .or_patterns
+                    .iter()
+                    .any(|pattern| pattern.iter().all(|symbol| is_accepted(symbol, &rule_set)));
+
+            if is_accepted_rule {
+                rule_set.insert(rule.non_terminal_symbol_name.clone());
+                retry = true;
+            }
+        }
+
+        if !retry {
+            break;
+        }
+    }
+
+    return rule_set;
+}
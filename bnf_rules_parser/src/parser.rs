@@ -1,5 +1,8 @@
-use crate::lexer::{Token, TokenPosition, UnexpectedCharacter};
-use crate::{OPERATION_ACCEPT, OPERATION_GOTO, OPERATION_NONE, OPERATION_REDUCE, OPERATION_SHIFT};
+use crate::lexer::{
+    OnDemandScanner, Token, TokenPosition, UnexpectedCharacter, EOF_SYMBOL_ID, ERROR_SYMBOL_ID,
+};
+use crate::table::ParseTable;
+use crate::{OPERATION_ACCEPT, OPERATION_NONE, OPERATION_REDUCE, OPERATION_SHIFT};
 use either::Either;
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -9,11 +12,21 @@ pub enum ASTNode {
         symbol_name: String,
         children: Vec<ASTNode>,
         position: TokenPosition,
+        /// The label in the pattern of the parent, e.g. 'lhs' of 'binop ::= lhs:expr "+" rhs:expr'.
+        label: Option<String>,
     },
     Terminal {
         internal_symbol_id: Option<u32>,
         text: String,
         position: TokenPosition,
+        label: Option<String>,
+    },
+    /// A region which the error recovery skipped, or a missing terminal symbol if 'children' is empty.
+    Error {
+        internal_symbol_id: Option<u32>,
+        children: Vec<ASTNode>,
+        position: TokenPosition,
+        label: Option<String>,
     },
 }
 
@@ -25,11 +38,19 @@ impl ASTNode {
                 symbol_name: _,
                 children: _,
                 position: _,
+                label: _,
             } => symbol_id.unwrap().clone(),
             ASTNode::Terminal {
                 internal_symbol_id: symbol_id,
                 text: _,
                 position: _,
+                label: _,
+            } => symbol_id.unwrap().clone(),
+            ASTNode::Error {
+                internal_symbol_id: symbol_id,
+                children: _,
+                position: _,
+                label: _,
             } => symbol_id.unwrap().clone(),
         };
     }
@@ -41,12 +62,103 @@ impl ASTNode {
                 symbol_name: _,
                 children: _,
                 position,
+                label: _,
             } => position.clone(),
             ASTNode::Terminal {
                 internal_symbol_id: _,
                 text: _,
                 position,
+                label: _,
             } => position.clone(),
+            ASTNode::Error {
+                internal_symbol_id: _,
+                children: _,
+                position,
+                label: _,
+            } => position.clone(),
+        };
+    }
+
+    pub fn get_label(&self) -> Option<&String> {
+        return match self {
+            ASTNode::NonTerminal { label, .. } => label.as_ref(),
+            ASTNode::Terminal { label, .. } => label.as_ref(),
+            ASTNode::Error { label, .. } => label.as_ref(),
+        };
+    }
+
+    /// Children of non-terminal symbols and error nodes, empty for terminal symbols.
+    pub fn get_children(&self) -> &[ASTNode] {
+        return match self {
+            ASTNode::NonTerminal { children, .. } => children,
+            ASTNode::Terminal { .. } => &[],
+            ASTNode::Error { children, .. } => children,
+        };
+    }
+
+    /// The first child labelled by 'label', e.g. 'lhs' of 'binop ::= lhs:expr "+" rhs:expr'.
+    pub fn child(&self, label: &str) -> Option<&ASTNode> {
+        return self
+            .get_children()
+            .iter()
+            .find(|child| child.get_label().is_some_and(|name| name == label));
+    }
+
+    /// All children labelled by 'label', e.g. 'arg' of 'args ::= arg:expr { "," arg:expr }'.
+    pub fn children_named(&self, label: &str) -> Vec<&ASTNode> {
+        return self
+            .get_children()
+            .iter()
+            .filter(|child| child.get_label().is_some_and(|name| name == label))
+            .collect();
+    }
+
+    fn set_label(&mut self, new_label: Option<String>) {
+        match self {
+            ASTNode::NonTerminal { label, .. } => *label = new_label,
+            ASTNode::Terminal { label, .. } => *label = new_label,
+            ASTNode::Error { label, .. } => *label = new_label,
+        }
+    }
+}
+
+/// A terminal symbol in values of '__parse_values', such as typed ASTs.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct TerminalNode {
+    pub text: String,
+    pub position: TokenPosition,
+}
+
+impl TerminalNode {
+    pub fn new(token: Token) -> Self {
+        return Self {
+            text: token.text.to_string(),
+            position: token.position,
+        };
+    }
+}
+
+/// A value of a symbol in the pattern which is reduced by '__parse_values'.
+#[derive(Debug, Clone)]
+pub enum SymbolValue<V> {
+    Terminal(TerminalNode),
+    NonTerminal(V),
+}
+
+impl<V> SymbolValue<V> {
+    /// Panics if it is not a terminal symbol.
+    pub fn into_terminal(self) -> TerminalNode {
+        return match self {
+            SymbolValue::Terminal(node) => node,
+            _ => panic!("A terminal symbol is expected."),
+        };
+    }
+
+    /// Panics if it is not a non-terminal symbol.
+    pub fn into_value(self) -> V {
+        return match self {
+            SymbolValue::NonTerminal(value) => value,
+            _ => panic!("A non-terminal symbol is expected."),
         };
     }
 }
@@ -59,9 +171,14 @@ pub enum Symbol {
 
 #[derive(Debug, Clone)]
 pub struct ParseError {
-    pub position: Option<TokenPosition>,
+    /// Boxed to keep 'Result<_, ParseError>' small.
+    pub position: Option<Box<TokenPosition>>,
     pub message: String,
     pub error_type: ParseErrorType,
+    /// Names of terminal symbols which are acceptable at the error, empty if unknown.
+    pub expected: Vec<String>,
+    /// Quoted text of the token at the error, or the name of the symbol if it has no text.
+    pub found: Option<String>,
 }
 
 impl ParseError {
@@ -70,11 +187,7 @@ impl ParseError {
             Some(token) => Some(token.position),
             _ => None,
         };
-        return Self {
-            position,
-            message,
-            error_type,
-        };
+        return Self::new_from_position(position, message, error_type);
     }
 
     pub fn new_from_position(
@@ -83,11 +196,19 @@ impl ParseError {
         error_type: ParseErrorType,
     ) -> Self {
         return Self {
-            position,
+            position: position.map(Box::new),
             message,
             error_type,
+            expected: Vec::new(),
+            found: None,
         };
     }
+
+    pub fn with_expected(mut self, expected: Vec<String>, found: String) -> Self {
+        self.expected = expected;
+        self.found = Some(found);
+        return self;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -97,68 +218,385 @@ pub enum ParseErrorType {
     InternalError,
 }
 
+/// Tables of the parser which are generated by 'bnf_rules!', see '__bnf_rules_tables' of the generated code.
+pub struct ParserTables {
+    /// Name of the rule of each pattern.
+    pub rule_pattern_name: &'static [&'static str],
+    pub lr_table: &'static ParseTable,
+    /// (symbol id of the rule, symbol ids of the pattern) of each pattern.
+    pub bnf_rules: &'static [(u32, &'static [u32])],
+    /// Labels of symbols in each pattern, "" for symbols without labels.
+    pub rule_labels: &'static [&'static [&'static str]],
+    /// Names of terminal symbols in error messages, empty for non-terminal symbols.
+    pub terminal_names: &'static [&'static str],
+}
+
 pub fn __parse(
     tokens: Result<Vec<Token>, UnexpectedCharacter>,
-    rule_pattern_name: &[&str],
-    lr_table: &[&[(usize, usize)]],
-    bnf_rules: &[(u32, &[u32])],
+    tables: &ParserTables,
+    start_state: usize,
 ) -> Result<ASTNode, ParseError> {
-    let mut tokens = match tokens {
+    let tokens = match tokens {
         Ok(tokens) => tokens,
-        Err(err) => {
-            return Err(ParseError::new_from_position(
-                Some(err.position),
-                "Unexpected token(Lexer).".to_string(),
-                ParseErrorType::UnexpectedToken,
-            ))
+        Err(err) => return Err(unexpected_character_error(err)),
+    };
+
+    let mut tokens = TokenReader::new_scanned(tokens);
+    return parse_tokens(&mut tokens, tables, start_state, None);
+}
+
+/// Parse with tokens of 'Lexer::scan_with_recovery', recovering from syntax errors.<br>
+/// Error tokens are ignored by the parser. A syntax error is recovered by 'error_symbol'
+/// of error productions if possible, as in yacc, otherwise it is repaired
+/// by inserting, deleting or substituting a token, or by skipping tokens to one of 'sync_symbols'.<br>
+/// Skipped regions and missing tokens are 'ASTNode::Error' in the result.
+pub fn __parse_with_errors(
+    tokens: (Vec<Token>, Vec<UnexpectedCharacter>),
+    tables: &ParserTables,
+    sync_symbols: &[u32],
+    error_symbol: Option<u32>,
+    start_state: usize,
+) -> (Option<ASTNode>, Vec<ParseError>) {
+    let (tokens, lexer_errors) = tokens;
+    let mut tokens = TokenReader::new_scanned(tokens);
+    let mut recovery = Recovery::new(sync_symbols, error_symbol);
+    let result = parse_tokens(&mut tokens, tables, start_state, Some(&mut recovery));
+    return collect_errors(lexer_errors, recovery.errors, result);
+}
+
+/// '__parse_with_errors' for '__parse_on_demand'.
+pub fn __parse_on_demand_with_errors(
+    scanner: OnDemandScanner,
+    tables: &ParserTables,
+    sync_symbols: &[u32],
+    error_symbol: Option<u32>,
+    start_state: usize,
+) -> (Option<ASTNode>, Vec<ParseError>) {
+    let mut tokens = TokenReader::OnDemand {
+        scanner: Box::new(scanner.with_recovery()),
+        lookahead: None,
+    };
+    let mut recovery = Recovery::new(sync_symbols, error_symbol);
+    let result = parse_tokens(&mut tokens, tables, start_state, Some(&mut recovery));
+
+    let lexer_errors = match &mut tokens {
+        TokenReader::OnDemand { scanner, .. } => scanner.take_errors(),
+        _ => Vec::new(),
+    };
+    return collect_errors(lexer_errors, recovery.errors, result);
+}
+
+fn collect_errors(
+    lexer_errors: Vec<UnexpectedCharacter>,
+    syntax_errors: Vec<ParseError>,
+    result: Result<ASTNode, ParseError>,
+) -> (Option<ASTNode>, Vec<ParseError>) {
+    let mut errors = lexer_errors
+        .into_iter()
+        .map(unexpected_character_error)
+        .collect::<Vec<_>>();
+    errors.extend(syntax_errors);
+
+    let node = match result {
+        Ok(node) => Some(node),
+        Err(error) => {
+            errors.push(error);
+            None
         }
     };
+    errors.sort_by_key(|error| {
+        error
+            .position
+            .as_ref()
+            .map(|position| position.start_position)
+    });
+
+    return (node, errors);
+}
+
+/// Parse with tokens which are scanned when the parser needs them.<br>
+/// Only terminal symbols which have actions in the current state are tried,
+/// and states which reduce for any lookahead do not scan it.
+pub fn __parse_on_demand(
+    scanner: OnDemandScanner,
+    tables: &ParserTables,
+    start_state: usize,
+) -> Result<ASTNode, ParseError> {
+    let mut tokens = TokenReader::OnDemand {
+        scanner: Box::new(scanner),
+        lookahead: None,
+    };
+    return parse_tokens(&mut tokens, tables, start_state, None);
+}
 
-    let last_token_position = match tokens.last() {
-        Some(token) => token.position.clone(),
-        _ => TokenPosition::new(0, 0, 0, 0),
+/// Parse computing a value by 'reduce' at each reduction, instead of building 'ASTNode'.<br>
+/// 'reduce' takes the pattern id and values of the symbols in the pattern.
+pub fn __parse_values<V>(
+    tokens: Result<Vec<Token>, UnexpectedCharacter>,
+    tables: &ParserTables,
+    start_state: usize,
+    reduce: &dyn Fn(usize, Vec<SymbolValue<V>>) -> V,
+) -> Result<V, ParseError> {
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        Err(err) => return Err(unexpected_character_error(err)),
     };
 
-    tokens.reverse();
+    let mut tokens = TokenReader::new_scanned(tokens);
+    return parse_values(&mut tokens, tables, start_state, reduce);
+}
+
+/// '__parse_values' for '__parse_on_demand'.
+pub fn __parse_values_on_demand<V>(
+    scanner: OnDemandScanner,
+    tables: &ParserTables,
+    start_state: usize,
+    reduce: &dyn Fn(usize, Vec<SymbolValue<V>>) -> V,
+) -> Result<V, ParseError> {
+    let mut tokens = TokenReader::OnDemand {
+        scanner: Box::new(scanner),
+        lookahead: None,
+    };
+    return parse_values(&mut tokens, tables, start_state, reduce);
+}
 
+fn unexpected_character_error(err: UnexpectedCharacter) -> ParseError {
+    return ParseError::new_from_position(
+        Some(err.position),
+        "Unexpected token(Lexer).".to_string(),
+        ParseErrorType::UnexpectedToken,
+    );
+}
+
+/// Tokens which are read by the parser.
+enum TokenReader<'lexer, 'input> {
+    Scanned {
+        /// All tokens in reverse order.
+        tokens: Vec<Token<'input>>,
+        /// Position of the EOF token.
+        last_token_position: TokenPosition,
+    },
+    OnDemand {
+        scanner: Box<OnDemandScanner<'lexer, 'input>>,
+        lookahead: Option<Token<'input>>,
+    },
+}
+
+impl<'lexer, 'input> TokenReader<'lexer, 'input> {
+    fn new_scanned(mut tokens: Vec<Token<'input>>) -> Self {
+        let last_token_position = match tokens.last() {
+            Some(token) => token.position.clone(),
+            _ => TokenPosition::new(0, 0, 0, 0),
+        };
+        tokens.reverse();
+
+        return TokenReader::Scanned {
+            tokens,
+            last_token_position,
+        };
+    }
+
+    /// Returns the next token, ignoring tokens of 'ERROR_SYMBOL_ID' which the lexer reported.<br>
+    /// If it is not scanned yet, it is scanned with terminal symbols acceptable in the state.
+    fn peek(&mut self, state: usize, lr_table: &ParseTable) -> Result<&Token<'input>, ParseError> {
+        return match self {
+            TokenReader::Scanned { tokens, .. } => {
+                while tokens
+                    .last()
+                    .is_some_and(|token| token.symbol_id == ERROR_SYMBOL_ID)
+                {
+                    tokens.pop();
+                }
+                get_tokens_last(tokens)
+            }
+            TokenReader::OnDemand { scanner, lookahead } => {
+                while lookahead
+                    .as_ref()
+                    .is_none_or(|token| token.symbol_id == ERROR_SYMBOL_ID)
+                {
+                    let is_acceptable = |symbol_id: u32| {
+                        lr_table.get_action(state, symbol_id as usize).0 != OPERATION_NONE
+                    };
+                    let token = scanner
+                        .next_token(&is_acceptable)
+                        .map_err(unexpected_character_error)?;
+                    *lookahead = Some(token);
+                }
+                Ok(lookahead.as_ref().unwrap())
+            }
+        };
+    }
+
+    fn pop(&mut self) -> Result<Token<'input>, ParseError> {
+        return match self {
+            TokenReader::Scanned { tokens, .. } => pop_token(tokens),
+            TokenReader::OnDemand { lookahead, .. } => match lookahead.take() {
+                Some(token) => Ok(token),
+                _ => Err(ParseError::new(
+                    None,
+                    "Elements of the tokens are missing.".to_string(),
+                    ParseErrorType::InvalidSyntax,
+                )),
+            },
+        };
+    }
+
+    /// Symbol ids of the next tokens to EOF at most 'count', without reading them.<br>
+    /// Tokens after the lookahead are scanned by a copy of the scanner with all terminal symbols.
+    fn peek_symbols(
+        &mut self,
+        state: usize,
+        lr_table: &ParseTable,
+        count: usize,
+    ) -> Result<Vec<u32>, ParseError> {
+        let first_token = self.peek(state, lr_table)?.clone();
+        let mut symbols = vec![first_token.symbol_id];
+        if first_token.is_eof {
+            return Ok(symbols);
+        }
+
+        match self {
+            TokenReader::Scanned { tokens, .. } => {
+                for token in tokens.iter().rev().skip(1) {
+                    if symbols.len() == count {
+                        break;
+                    }
+                    if token.symbol_id != ERROR_SYMBOL_ID {
+                        symbols.push(token.symbol_id);
+                    }
+                }
+            }
+            TokenReader::OnDemand { scanner, .. } => {
+                let mut scanner = scanner.clone();
+                while symbols.len() < count {
+                    let token = match scanner.next_token(&|_| true) {
+                        Ok(token) => token,
+                        _ => break,
+                    };
+                    if token.symbol_id == ERROR_SYMBOL_ID {
+                        continue;
+                    }
+                    symbols.push(token.symbol_id);
+                    if token.is_eof {
+                        break;
+                    }
+                }
+            }
+        }
+        return Ok(symbols);
+    }
+
+    fn has_lookahead(&self) -> bool {
+        return match self {
+            TokenReader::Scanned { .. } => true,
+            TokenReader::OnDemand { lookahead, .. } => lookahead.is_some(),
+        };
+    }
+
+    /// Position of the next token, or the end of the source.
+    fn current_position(&mut self) -> TokenPosition {
+        return match self {
+            TokenReader::Scanned {
+                tokens,
+                last_token_position,
+            } => match tokens.last() {
+                Some(token) => token.position.clone(),
+                _ => last_token_position.clone(),
+            },
+            TokenReader::OnDemand { scanner, lookahead } => match lookahead {
+                Some(token) => token.position.clone(),
+                _ => scanner.current_position(),
+            },
+        };
+    }
+}
+
+fn parse_tokens(
+    tokens: &mut TokenReader,
+    tables: &ParserTables,
+    start_state: usize,
+    mut recovery: Option<&mut Recovery>,
+) -> Result<ASTNode, ParseError> {
     let mut stack = Vec::<usize>::new();
     let mut reduced_buffer = Vec::<Either<Token, ASTNode>>::new();
+    // an error node for a missing terminal symbol, which is read before the next token
+    let mut inserted: Option<ASTNode> = None;
 
-    stack.push(0);
+    stack.push(start_state);
 
     loop {
-        let stack_last = get_stack_last(&stack, &tokens)?;
-        let symbol_id = get_tokens_last_symbol_id(&tokens)?;
-        let operation = &lr_table[stack_last][symbol_id as usize];
+        let stack_last = get_stack_last(&stack, tokens)?;
+        let operation = match tables.lr_table.get_default_reduction(stack_last) {
+            // the lookahead is scanned later in the state after the reduction
+            Some(pattern_id) if inserted.is_none() && !tokens.has_lookahead() => {
+                (OPERATION_REDUCE, pattern_id)
+            }
+            _ => {
+                let symbol_id = match &inserted {
+                    Some(node) => node.get_symbol_id(),
+                    _ => tokens.peek(stack_last, tables.lr_table)?.symbol_id,
+                };
+                tables.lr_table.get_action(stack_last, symbol_id as usize)
+            }
+        };
 
         if operation.0 == OPERATION_NONE {
-            let position = match tokens.last() {
-                Some(token) => Some(token.position.clone()),
-                _ => None,
+            let token = tokens.peek(stack_last, tables.lr_table)?;
+            let error = syntax_error(token, stack_last, tables.lr_table, tables.terminal_names);
+
+            let recovery = match &mut recovery {
+                Some(recovery) => recovery,
+                _ => return Err(error),
             };
-            return Err(ParseError::new_from_position(
-                position,
-                "Invalid operation.".to_string(),
-                ParseErrorType::InvalidSyntax,
-            ));
+            recovery.report(error);
+
+            if !recover(
+                &mut stack,
+                &mut reduced_buffer,
+                &mut inserted,
+                tokens,
+                tables,
+                recovery,
+            )? {
+                // the rest cannot be parsed, all nodes are returned in an error node
+                let mut children = reduced_buffer
+                    .into_iter()
+                    .map(into_node)
+                    .collect::<Vec<_>>();
+                while let Ok(token) = tokens.pop() {
+                    if token.is_eof {
+                        break;
+                    }
+                    children.push(into_node(Either::Left(token)));
+                }
+                let mut node = error_node(None, children, tokens.current_position());
+                unset_internal_symbol_id(&mut node);
+                return Ok(node);
+            }
+            continue;
         }
 
         let operation_argument = operation.1;
 
         match operation.0 {
             OPERATION_SHIFT => {
-                let popped_token = pop_token(&mut tokens)?;
-                reduced_buffer.push(Either::Left(popped_token));
+                match inserted.take() {
+                    Some(node) => reduced_buffer.push(Either::Right(node)),
+                    _ => {
+                        let popped_token = tokens.pop()?;
+                        reduced_buffer.push(Either::Left(popped_token));
+                    }
+                }
 
                 stack.push(operation_argument);
             }
             OPERATION_REDUCE => {
                 let reduce_rule_id = operation_argument;
-                let rule = &bnf_rules[reduce_rule_id];
+                let rule = &tables.bnf_rules[reduce_rule_id];
                 let rule_pattern = rule.1;
                 let right_side_count = rule_pattern.len();
 
-                if reduced_buffer.len() < right_side_count {
+                if stack.len() <= right_side_count {
                     return Err(ParseError::new_from_position(
                         get_buffer_position(&reduced_buffer),
                         "Invalid syntax.".to_string(),
@@ -166,131 +604,581 @@ pub fn __parse(
                     ));
                 }
 
+                // error nodes of the recovery between symbols are also children
                 let mut buffer = Vec::<Either<Token, ASTNode>>::new();
-                for _ in 0..right_side_count {
-                    buffer.push(reduced_buffer.pop().unwrap());
-
-                    if stack.pop().is_none() {
-                        return Err(ParseError::new_from_position(
-                            get_buffer_position(&buffer),
-                            "Invalid syntax.".to_string(),
-                            ParseErrorType::InvalidSyntax,
-                        ));
+                let mut symbol_count = 0;
+                while symbol_count < right_side_count {
+                    let token_or_node = match reduced_buffer.pop() {
+                        Some(token_or_node) => token_or_node,
+                        _ => {
+                            return Err(ParseError::new_from_position(
+                                get_buffer_position(&buffer),
+                                "Invalid syntax.".to_string(),
+                                ParseErrorType::InvalidSyntax,
+                            ))
+                        }
+                    };
+                    if !is_recovered_region(&token_or_node) {
+                        symbol_count += 1;
+                        stack.pop();
                     }
+                    buffer.push(token_or_node);
                 }
                 buffer.reverse();
 
                 let mut reduce_children = Vec::<ASTNode>::new();
 
                 let mut position = if buffer.len() == 0 {
-                    match tokens.last() {
-                        Some(token) => {
-                            let mut position = token.position.clone();
-                            position.text_length = 0;
-                            position
-                        }
-                        _ => last_token_position.clone(),
-                    }
+                    let mut position = tokens.current_position();
+                    position.text_length = 0;
+                    position.end_line = position.line;
+                    position.end_column = position.column;
+                    position
                 } else {
                     TokenPosition::marge_start_position()
                 };
 
-                for i in 0..buffer.len() {
-                    let token_or_node = &buffer[i];
-                    let symbol_id = get_token_or_node_symbol_id(token_or_node);
-                    let pattern_symbol_id = rule_pattern[i];
-
-                    if symbol_id != pattern_symbol_id {
-                        return Err(ParseError::new_from_position(
-                            get_token_or_node_position(Some(&token_or_node)),
-                            "Invalid syntax.".to_string(),
-                            ParseErrorType::InvalidSyntax,
-                        ));
+                let mut symbol_index = 0;
+                for token_or_node in buffer.into_iter() {
+                    let mut label = None;
+                    if !is_recovered_region(&token_or_node) {
+                        let symbol_id = get_token_or_node_symbol_id(&token_or_node);
+                        if symbol_id != rule_pattern[symbol_index] {
+                            return Err(ParseError::new_from_position(
+                                get_token_or_node_position(Some(&token_or_node)),
+                                "Invalid syntax.".to_string(),
+                                ParseErrorType::InvalidSyntax,
+                            ));
+                        }
+                        label = get_symbol_label(tables.rule_labels, reduce_rule_id, symbol_index);
+                        symbol_index += 1;
                     }
 
-                    match token_or_node {
-                        Either::Left(token) => {
-                            let node = ASTNode::Terminal {
-                                internal_symbol_id: Some(symbol_id),
-                                text: token.text.to_string(),
-                                position: token.position.clone(),
-                            };
-                            position.marge(&node.get_position());
-                            reduce_children.push(node);
-                        }
-                        Either::Right(node) => {
-                            position.marge(&node.get_position());
-
-                            match node {
-                                ASTNode::NonTerminal {
-                                    internal_symbol_id: _,
-                                    symbol_name,
-                                    children,
-                                    position: _,
-                                } => {
-                                    if symbol_name.starts_with(" ") {
-                                        for child in children.iter() {
-                                            reduce_children.push(child.clone());
-                                        }
-                                    } else {
-                                        reduce_children.push(node.clone());
-                                    }
-                                }
-                                ASTNode::Terminal {
-                                    internal_symbol_id: _,
-                                    text: _,
-                                    position: _,
-                                } => {
-                                    reduce_children.push(node.clone());
+                    let mut node = into_node(token_or_node);
+                    position.marge(&node.get_position());
+
+                    match node {
+                        ASTNode::NonTerminal {
+                            internal_symbol_id: _,
+                            symbol_name,
+                            children,
+                            position: _,
+                            label: _,
+                        } if symbol_name.starts_with(" ") => {
+                            // the label of a group is given to its children without labels
+                            for mut child in children.into_iter() {
+                                if child.get_label().is_none() {
+                                    child.set_label(label.clone());
                                 }
+                                reduce_children.push(child);
                             }
                         }
-                    };
+                        _ => {
+                            node.set_label(label);
+                            reduce_children.push(node);
+                        }
+                    }
                 }
 
                 let rule_root_symbol_id = rule.0;
-                let rule_name = rule_pattern_name[reduce_rule_id].to_string();
+                let rule_name = tables.rule_pattern_name[reduce_rule_id].to_string();
 
                 let node = ASTNode::NonTerminal {
                     internal_symbol_id: Some(rule_root_symbol_id),
                     symbol_name: rule_name,
                     children: reduce_children,
                     position,
+                    label: None,
                 };
 
                 reduced_buffer.push(Either::Right(node));
 
-                let stack_last = get_stack_last(&stack, &tokens)?;
+                let stack_last = get_stack_last(&stack, tokens)?;
 
-                let operation = &lr_table[stack_last][rule_root_symbol_id as usize];
+                let next_state = match tables
+                    .lr_table
+                    .get_goto(stack_last, rule_root_symbol_id as usize)
+                {
+                    Some(next_state) => next_state,
+                    _ => {
+                        return Err(ParseError::new_from_position(
+                            get_buffer_position(&reduced_buffer),
+                            "Invalid operation.".to_string(),
+                            ParseErrorType::InvalidSyntax,
+                        ))
+                    }
+                };
 
-                if operation.0 != OPERATION_GOTO {
+                stack.push(next_state);
+            }
+            OPERATION_ACCEPT => break,
+            _ => {}
+        }
+    }
+
+    // error nodes before or after the root
+    let mut leading_errors = Vec::<ASTNode>::new();
+    let mut root = None;
+    let mut trailing_errors = Vec::<ASTNode>::new();
+    for token_or_node in reduced_buffer.into_iter() {
+        if is_recovered_region(&token_or_node) {
+            match root {
+                Some(_) => trailing_errors.push(into_node(token_or_node)),
+                _ => leading_errors.push(into_node(token_or_node)),
+            }
+        } else if root.is_none() {
+            root = Some(into_node(token_or_node));
+        } else {
+            root = None;
+            break;
+        }
+    }
+
+    let mut node = match root {
+        Some(node) => node,
+        _ => {
+            return Err(ParseError::new_from_position(
+                None,
+                "May be internal error. reduce_buffer.len() is not 1.".to_string(),
+                ParseErrorType::InternalError,
+            ))
+        }
+    };
+    if let ASTNode::NonTerminal {
+        children, position, ..
+    } = &mut node
+    {
+        for error in leading_errors.iter().chain(trailing_errors.iter()) {
+            position.marge(&error.get_position());
+        }
+        leading_errors.append(children);
+        leading_errors.append(&mut trailing_errors);
+        *children = leading_errors;
+    }
+    unset_internal_symbol_id(&mut node);
+
+    return Ok(node);
+}
+
+/// 'parse_tokens' without the error recovery, which keeps values of 'reduce' instead of nodes.
+fn parse_values<V>(
+    tokens: &mut TokenReader,
+    tables: &ParserTables,
+    start_state: usize,
+    reduce: &dyn Fn(usize, Vec<SymbolValue<V>>) -> V,
+) -> Result<V, ParseError> {
+    let mut stack = vec![start_state];
+    let mut values = Vec::<SymbolValue<V>>::new();
+
+    loop {
+        let stack_last = get_stack_last(&stack, tokens)?;
+        let operation = match tables.lr_table.get_default_reduction(stack_last) {
+            Some(pattern_id) if !tokens.has_lookahead() => (OPERATION_REDUCE, pattern_id),
+            _ => {
+                let symbol_id = tokens.peek(stack_last, tables.lr_table)?.symbol_id;
+                tables.lr_table.get_action(stack_last, symbol_id as usize)
+            }
+        };
+
+        match operation.0 {
+            OPERATION_SHIFT => {
+                let token = tokens.pop()?;
+                values.push(SymbolValue::Terminal(TerminalNode::new(token)));
+                stack.push(operation.1);
+            }
+            OPERATION_REDUCE => {
+                let rule = &tables.bnf_rules[operation.1];
+                let right_side_count = rule.1.len();
+
+                if stack.len() <= right_side_count || values.len() < right_side_count {
                     return Err(ParseError::new_from_position(
-                        get_buffer_position(&reduced_buffer),
-                        "Invalid operation.".to_string(),
+                        Some(tokens.current_position()),
+                        "Invalid syntax.".to_string(),
                         ParseErrorType::InvalidSyntax,
                     ));
                 }
+                stack.truncate(stack.len() - right_side_count);
+                let children = values.split_off(values.len() - right_side_count);
+                values.push(SymbolValue::NonTerminal(reduce(operation.1, children)));
 
-                stack.push(operation.1);
+                let stack_last = get_stack_last(&stack, tokens)?;
+                match tables.lr_table.get_goto(stack_last, rule.0 as usize) {
+                    Some(next_state) => stack.push(next_state),
+                    _ => {
+                        return Err(ParseError::new_from_position(
+                            Some(tokens.current_position()),
+                            "Invalid operation.".to_string(),
+                            ParseErrorType::InvalidSyntax,
+                        ))
+                    }
+                }
             }
             OPERATION_ACCEPT => break,
-            _ => {}
+            _ => {
+                let token = tokens.peek(stack_last, tables.lr_table)?;
+                return Err(syntax_error(
+                    token,
+                    stack_last,
+                    tables.lr_table,
+                    tables.terminal_names,
+                ));
+            }
         }
     }
 
-    if reduced_buffer.len() != 1 {
-        return Err(ParseError::new_from_position(
+    return match values.pop() {
+        Some(SymbolValue::NonTerminal(value)) if values.is_empty() => Ok(value),
+        _ => Err(ParseError::new_from_position(
             None,
-            "May be internal error. reduce_buffer.len() is not 1.".to_string(),
+            "May be internal error. values.len() is not 1.".to_string(),
             ParseErrorType::InternalError,
-        ));
+        )),
+    };
+}
+
+/// Settings and results of the error recovery in 'parse_tokens'.
+struct Recovery<'a> {
+    /// Terminal symbols where the panic mode restarts parsing, such as ';' and '}'.
+    sync_symbols: &'a [u32],
+    /// 'error' of error productions, if the grammar has them.
+    error_symbol: Option<u32>,
+    errors: Vec<ParseError>,
+    /// Whether the last error is at the same token as the previous one,
+    /// then the error productions are not used again.
+    is_repeated: bool,
+}
+
+impl<'a> Recovery<'a> {
+    fn new(sync_symbols: &'a [u32], error_symbol: Option<u32>) -> Self {
+        return Self {
+            sync_symbols,
+            error_symbol,
+            errors: Vec::new(),
+            is_repeated: false,
+        };
     }
 
-    let mut node = reduced_buffer.remove(0).right().unwrap();
-    unset_internal_symbol_id(&mut node);
+    fn report(&mut self, error: ParseError) {
+        let start_position = |error: &ParseError| {
+            error
+                .position
+                .as_ref()
+                .map(|position| position.start_position)
+        };
+        self.is_repeated = self
+            .errors
+            .last()
+            .is_some_and(|last| start_position(last) == start_position(&error));
+        if !self.is_repeated {
+            self.errors.push(error);
+        }
+    }
+}
 
-    return Ok(node);
+/// Number of tokens which the parser must read after a repair of the input.
+const REPAIR_WINDOW: usize = 3;
+
+/// Recovers from a syntax error by the error productions, see 'recover_by_error_symbol'.<br>
+/// Otherwise, repairs the input by inserting, deleting or substituting one token.<br>
+/// If no repair works, tokens are skipped until a sync symbol,
+/// and states are popped, if needed, until the parser can read the next tokens (panic mode).<br>
+/// Returns false if the rest of the input cannot be parsed.
+fn recover<'input>(
+    stack: &mut Vec<usize>,
+    reduced_buffer: &mut Vec<Either<Token<'input>, ASTNode>>,
+    inserted: &mut Option<ASTNode>,
+    tokens: &mut TokenReader<'_, 'input>,
+    tables: &ParserTables,
+    recovery: &Recovery,
+) -> Result<bool, ParseError> {
+    if let Some(error_symbol) = recovery.error_symbol.filter(|_| !recovery.is_repeated) {
+        if let Some(can_continue) =
+            recover_by_error_symbol(stack, reduced_buffer, tokens, tables.lr_table, error_symbol)?
+        {
+            return Ok(can_continue);
+        }
+    }
+
+    let state = get_stack_last(stack, tokens)?;
+    let window = tokens.peek_symbols(state, tables.lr_table, REPAIR_WINDOW + 1)?;
+    let current_token = tokens.peek(state, tables.lr_table)?.clone();
+
+    // terminal symbols which can be inserted or substituted, except EOF
+    let candidates = (0..tables.terminal_names.len() as u32)
+        .filter(|symbol_id| {
+            *symbol_id != EOF_SYMBOL_ID
+                && !tables.terminal_names[*symbol_id as usize].is_empty()
+                && tables.lr_table.get_action(state, *symbol_id as usize).0 != OPERATION_NONE
+        })
+        .collect::<Vec<_>>();
+    let mut empty_position = current_token.position.clone();
+    empty_position.text_length = 0;
+    empty_position.end_line = empty_position.line;
+    empty_position.end_column = empty_position.column;
+
+    // insertion
+    let rest = &window[..window.len().min(REPAIR_WINDOW)];
+    for symbol_id in candidates.iter() {
+        if can_parse(
+            stack,
+            Some(*symbol_id),
+            rest,
+            tables.lr_table,
+            tables.bnf_rules,
+        ) {
+            *inserted = Some(error_node(Some(*symbol_id), vec![], empty_position));
+            return Ok(true);
+        }
+    }
+
+    if !current_token.is_eof {
+        let rest = &window[1..];
+
+        // deletion
+        if can_parse(stack, None, rest, tables.lr_table, tables.bnf_rules) {
+            let token = tokens.pop()?;
+            let position = token.position.clone();
+            let node = error_node(None, vec![into_node(Either::Left(token))], position);
+            reduced_buffer.push(Either::Right(node));
+            return Ok(true);
+        }
+
+        // substitution
+        for symbol_id in candidates.iter() {
+            if can_parse(
+                stack,
+                Some(*symbol_id),
+                rest,
+                tables.lr_table,
+                tables.bnf_rules,
+            ) {
+                let token = tokens.pop()?;
+                let position = token.position.clone();
+                let children = vec![into_node(Either::Left(token))];
+                *inserted = Some(error_node(Some(*symbol_id), children, position));
+                return Ok(true);
+            }
+        }
+    }
+
+    // panic mode
+    let mut skipped = Vec::<ASTNode>::new();
+    loop {
+        let state = get_stack_last(stack, tokens)?;
+        let window = tokens.peek_symbols(state, tables.lr_table, REPAIR_WINDOW)?;
+
+        // states are not popped if the parser can read the tokens after the skipped ones
+        for depth in 0..stack.len() {
+            if depth == 0 && skipped.is_empty() {
+                continue;
+            }
+            if !can_parse(
+                &stack[..stack.len() - depth],
+                None,
+                &window,
+                tables.lr_table,
+                tables.bnf_rules,
+            ) {
+                continue;
+            }
+
+            let mut children = Vec::<ASTNode>::new();
+            let mut symbol_count = 0;
+            while symbol_count < depth {
+                let token_or_node = reduced_buffer.pop().unwrap();
+                if !is_recovered_region(&token_or_node) {
+                    symbol_count += 1;
+                }
+                children.push(into_node(token_or_node));
+            }
+            children.reverse();
+            children.append(&mut skipped);
+            stack.truncate(stack.len() - depth);
+
+            let node = error_node(None, children, empty_position);
+            reduced_buffer.push(Either::Right(node));
+            return Ok(true);
+        }
+
+        if window
+            .first()
+            .is_none_or(|symbol_id| *symbol_id == EOF_SYMBOL_ID)
+        {
+            if !skipped.is_empty() {
+                let node = error_node(None, skipped, empty_position);
+                reduced_buffer.push(Either::Right(node));
+            }
+            return Ok(false);
+        }
+
+        // skip tokens to the next sync symbol
+        loop {
+            let token = tokens.pop()?;
+            let is_sync_symbol = recovery.sync_symbols.contains(&token.symbol_id);
+            skipped.push(into_node(Either::Left(token)));
+
+            let state = get_stack_last(stack, tokens)?;
+            if is_sync_symbol || tokens.peek(state, tables.lr_table)?.is_eof {
+                break;
+            }
+        }
+    }
+}
+
+/// Pops states until one can shift 'error', shifts it,
+/// and discards tokens until the parser can read the next token, as in yacc.<br>
+/// The node of 'error' has the popped symbols and the discarded tokens.<br>
+/// Returns None if no state can shift 'error', or false if the rest of the input cannot be parsed.
+fn recover_by_error_symbol<'input>(
+    stack: &mut Vec<usize>,
+    reduced_buffer: &mut Vec<Either<Token<'input>, ASTNode>>,
+    tokens: &mut TokenReader<'_, 'input>,
+    lr_table: &ParseTable,
+    error_symbol: u32,
+) -> Result<Option<bool>, ParseError> {
+    let depth = (0..stack.len()).find(|depth| {
+        let state = stack[stack.len() - 1 - depth];
+        lr_table.get_action(state, error_symbol as usize).0 == OPERATION_SHIFT
+    });
+    let depth = match depth {
+        Some(depth) => depth,
+        _ => return Ok(None),
+    };
+    let state = stack[stack.len() - 1 - depth];
+    let next_state = lr_table.get_action(state, error_symbol as usize).1;
+
+    // other recoveries may insert a missing token at the end
+    let token = tokens.peek(state, lr_table)?;
+    if token.is_eof
+        && lr_table.get_default_reduction(next_state).is_none()
+        && lr_table.get_action(next_state, token.symbol_id as usize).0 == OPERATION_NONE
+    {
+        return Ok(None);
+    }
+
+    let mut children = Vec::<ASTNode>::new();
+    let mut symbol_count = 0;
+    while symbol_count < depth {
+        let token_or_node = reduced_buffer.pop().unwrap();
+        if !is_recovered_region(&token_or_node) {
+            symbol_count += 1;
+        }
+        children.push(into_node(token_or_node));
+    }
+    children.reverse();
+    stack.truncate(stack.len() - depth);
+
+    let mut empty_position = tokens.current_position();
+    empty_position.text_length = 0;
+    empty_position.end_line = empty_position.line;
+    empty_position.end_column = empty_position.column;
+
+    // the next state may reduce without the lookahead
+    let can_continue = loop {
+        if lr_table.get_default_reduction(next_state).is_some() {
+            break true;
+        }
+        let token = tokens.peek(next_state, lr_table)?;
+        if lr_table.get_action(next_state, token.symbol_id as usize).0 != OPERATION_NONE {
+            break true;
+        }
+        if token.is_eof {
+            break false;
+        }
+        children.push(into_node(Either::Left(tokens.pop()?)));
+    };
+
+    if can_continue {
+        let node = error_node(Some(error_symbol), children, empty_position);
+        reduced_buffer.push(Either::Right(node));
+        stack.push(next_state);
+    } else if !children.is_empty() {
+        let node = error_node(None, children, empty_position);
+        reduced_buffer.push(Either::Right(node));
+    }
+    return Ok(Some(can_continue));
+}
+
+/// Whether the parser in the stack can read all the symbols without errors.
+fn can_parse(
+    stack: &[usize],
+    inserted: Option<u32>,
+    symbols: &[u32],
+    lr_table: &ParseTable,
+    bnf_rules: &[(u32, &[u32])],
+) -> bool {
+    let mut stack = stack.to_vec();
+    for symbol_id in inserted.iter().chain(symbols.iter()) {
+        loop {
+            let state = match stack.last() {
+                Some(state) => *state,
+                _ => return false,
+            };
+            let (operation, argument) = lr_table.get_action(state, *symbol_id as usize);
+            match operation {
+                OPERATION_SHIFT => {
+                    stack.push(argument);
+                    break;
+                }
+                OPERATION_REDUCE => {
+                    let (root_symbol_id, pattern) = bnf_rules[argument];
+                    if stack.len() <= pattern.len() {
+                        return false;
+                    }
+                    stack.truncate(stack.len() - pattern.len());
+
+                    match lr_table.get_goto(*stack.last().unwrap(), root_symbol_id as usize) {
+                        Some(next_state) => stack.push(next_state),
+                        _ => return false,
+                    }
+                }
+                OPERATION_ACCEPT => return true,
+                _ => return false,
+            }
+        }
+    }
+    return true;
+}
+
+/// Whether it is an error node which the recovery made for skipped tokens,
+/// which is not a symbol of patterns.
+fn is_recovered_region(token_or_node: &Either<Token, ASTNode>) -> bool {
+    return matches!(
+        token_or_node,
+        Either::Right(ASTNode::Error {
+            internal_symbol_id: None,
+            ..
+        })
+    );
+}
+
+fn error_node(
+    internal_symbol_id: Option<u32>,
+    children: Vec<ASTNode>,
+    empty_position: TokenPosition,
+) -> ASTNode {
+    let mut position = empty_position;
+    for child in children.iter() {
+        position.marge(&child.get_position());
+    }
+    return ASTNode::Error {
+        internal_symbol_id,
+        children,
+        position,
+        label: None,
+    };
+}
+
+fn into_node(token_or_node: Either<Token, ASTNode>) -> ASTNode {
+    return match token_or_node {
+        Either::Left(token) => ASTNode::Terminal {
+            internal_symbol_id: Some(token.symbol_id),
+            text: token.text.to_string(),
+            position: token.position,
+            label: None,
+        },
+        Either::Right(node) => node,
+    };
 }
 
 fn unset_internal_symbol_id(node: &mut ASTNode) {
@@ -300,6 +1188,7 @@ fn unset_internal_symbol_id(node: &mut ASTNode) {
             symbol_name: _,
             children,
             position: _,
+            label: _,
         } => {
             *internal_symbol_id = None;
             for child in children.iter_mut() {
@@ -310,10 +1199,88 @@ fn unset_internal_symbol_id(node: &mut ASTNode) {
             internal_symbol_id,
             text: _,
             position: _,
+            label: _,
         } => {
             *internal_symbol_id = None;
         }
+        ASTNode::Error {
+            internal_symbol_id,
+            children,
+            position: _,
+            label: _,
+        } => {
+            *internal_symbol_id = None;
+            for child in children.iter_mut() {
+                unset_internal_symbol_id(child);
+            }
+        }
+    }
+}
+
+/// Names of terminal symbols which have actions in the state, in order of symbol ids.
+fn expected_terminal_names(
+    state: usize,
+    lr_table: &ParseTable,
+    terminal_names: &[&str],
+) -> Vec<String> {
+    let mut expected = Vec::<String>::new();
+    for (symbol_id, name) in terminal_names.iter().enumerate() {
+        if name.is_empty() {
+            continue;
+        }
+        if lr_table.get_action(state, symbol_id).0 != OPERATION_NONE {
+            expected.push(name.to_string());
+        }
     }
+    return expected;
+}
+
+fn syntax_error(
+    token: &Token,
+    state: usize,
+    lr_table: &ParseTable,
+    terminal_names: &[&str],
+) -> ParseError {
+    let expected = expected_terminal_names(state, lr_table, terminal_names);
+    let found = found_token_name(token, terminal_names);
+    return ParseError::new_from_position(
+        Some(token.position.clone()),
+        expected_message(&expected, &found),
+        ParseErrorType::InvalidSyntax,
+    )
+    .with_expected(expected, found);
+}
+
+fn found_token_name(token: &Token, terminal_names: &[&str]) -> String {
+    return if token.is_eof || token.text.is_empty() {
+        terminal_names[token.symbol_id as usize].to_string()
+    } else {
+        format!("{:?}", token.text)
+    };
+}
+
+/// e.g. 'expected one of "+", ")" but found "]"'
+fn expected_message(expected: &Vec<String>, found: &String) -> String {
+    return match expected.len() {
+        0 => format!("unexpected {}", found),
+        1 => format!("expected {} but found {}", expected[0], found),
+        _ => format!(
+            "expected one of {} but found {}",
+            expected.join(", "),
+            found
+        ),
+    };
+}
+
+/// The label of the symbol in the pattern, 'rule_labels' has "" for symbols without labels.
+fn get_symbol_label(rule_labels: &[&[&str]], pattern_id: usize, index: usize) -> Option<String> {
+    return match rule_labels
+        .get(pattern_id)
+        .and_then(|labels| labels.get(index))
+    {
+        Some(label) if !label.is_empty() => Some(label.to_string()),
+        _ => None,
+    };
 }
 
 fn get_token_or_node_symbol_id(token_or_node: &Either<Token, ASTNode>) -> u32 {
@@ -347,12 +1314,12 @@ fn get_buffer_position(buffer: &Vec<Either<Token, ASTNode>>) -> Option<TokenPosi
     return Some(merged_position);
 }
 
-fn get_stack_last(stack: &Vec<usize>, tokens: &Vec<Token>) -> Result<usize, ParseError> {
+fn get_stack_last(stack: &Vec<usize>, tokens: &mut TokenReader) -> Result<usize, ParseError> {
     return match stack.last() {
         Some(last) => Ok(*last),
         _ => {
-            return Err(ParseError::new(
-                tokens.first().cloned(),
+            return Err(ParseError::new_from_position(
+                Some(tokens.current_position()),
                 "Elements of the parser stack are missing.".to_string(),
                 ParseErrorType::InvalidSyntax,
             ))
@@ -387,9 +1354,3 @@ fn pop_token<'input, 'a>(tokens: &'a mut Vec<Token<'input>>) -> Result<Token<'in
         }
     };
 }
-
-fn get_tokens_last_symbol_id(tokens: &Vec<Token>) -> Result<u32, ParseError> {
-    let token = get_tokens_last(tokens)?;
-    return Ok(token.symbol_id);
-}
-
@@ -0,0 +1,61 @@
+/// A set of symbol ids, which is used for lookaheads of LR items.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct SymbolSet {
+    bits: Vec<u64>,
+}
+
+impl SymbolSet {
+    pub fn new(symbol_count: usize) -> Self {
+        return Self {
+            bits: vec![0; symbol_count.div_ceil(64)],
+        };
+    }
+
+    /// Returns true if the id is newly inserted.
+    pub fn insert(&mut self, id: usize) -> bool {
+        let (index, mask) = (id / 64, 1 << (id % 64));
+        let is_inserted = self.bits[index] & mask == 0;
+        self.bits[index] |= mask;
+        return is_inserted;
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        return match self.bits.get(id / 64) {
+            Some(bits) => bits & (1 << (id % 64)) != 0,
+            _ => false,
+        };
+    }
+
+    /// Returns true if some ids are newly inserted.
+    pub fn union_with(&mut self, other: &SymbolSet) -> bool {
+        let mut is_changed = false;
+        for (bits, other_bits) in self.bits.iter_mut().zip(other.bits.iter()) {
+            if *bits | other_bits != *bits {
+                *bits |= other_bits;
+                is_changed = true;
+            }
+        }
+        return is_changed;
+    }
+
+    pub fn is_disjoint(&self, other: &SymbolSet) -> bool {
+        return self
+            .bits
+            .iter()
+            .zip(other.bits.iter())
+            .all(|(bits, other_bits)| bits & other_bits == 0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.bits.iter().all(|bits| *bits == 0);
+    }
+
+    /// Ids in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        return self.bits.iter().enumerate().flat_map(|(index, bits)| {
+            (0..64)
+                .filter(move |bit| bits & (1 << bit) != 0)
+                .map(move |bit| index * 64 + bit)
+        });
+    }
+}
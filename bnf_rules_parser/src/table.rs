@@ -0,0 +1,262 @@
+use crate::{Operation, OPERATION_GOTO, OPERATION_NONE, OPERATION_REDUCE};
+
+/// The parse table which is generated by 'bnf_rules!'.
+pub enum ParseTable {
+    /// (operation, argument) for each state and symbol, specified by '#[compress_table = false]'.
+    Dense(&'static [&'static [(usize, usize)]]),
+    Compressed(CompressedTable),
+}
+
+impl ParseTable {
+    /// Returns (operation, argument) for the state and the terminal symbol.
+    pub fn get_action(&self, state: usize, symbol_id: usize) -> (usize, usize) {
+        return match self {
+            ParseTable::Dense(table) => table[state][symbol_id],
+            ParseTable::Compressed(table) => table.get_action(state, symbol_id),
+        };
+    }
+
+    /// Returns the next state for the state and the non-terminal symbol.
+    pub fn get_goto(&self, state: usize, symbol_id: usize) -> Option<usize> {
+        return match self {
+            ParseTable::Dense(table) => {
+                let (operation, argument) = table[state][symbol_id];
+                if operation == OPERATION_GOTO {
+                    Some(argument)
+                } else {
+                    None
+                }
+            }
+            ParseTable::Compressed(table) => table.goto.get(state, symbol_id),
+        };
+    }
+
+    /// Returns the pattern id if the state reduces by it for any lookahead.
+    pub fn get_default_reduction(&self, state: usize) -> Option<usize> {
+        return match self {
+            ParseTable::Dense(_) => None,
+            ParseTable::Compressed(table) => match table.default_reductions.get(state) {
+                0 => None,
+                reduction => Some(reduction - 1),
+            },
+        };
+    }
+}
+
+/// ACTION and GOTO tables which are compressed by row displacement.
+pub struct CompressedTable {
+    /// Entries are 'argument * 8 + operation'.
+    pub action: DisplacementTable,
+    /// Entries are next states.
+    pub goto: DisplacementTable,
+    /// 'pattern id + 1' of the reduction for states which only reduce by one pattern, or 0.<br>
+    /// All of them are 0 unless '#[default_reductions = true]' is specified.
+    pub default_reductions: TableArray,
+}
+
+impl CompressedTable {
+    pub fn get_action(&self, state: usize, symbol_id: usize) -> (usize, usize) {
+        return match self.action.get(state, symbol_id) {
+            Some(entry) => (entry % 8, entry / 8),
+            _ => match self.default_reductions.get(state) {
+                0 => (OPERATION_NONE, 0),
+                reduction => (OPERATION_REDUCE, reduction - 1),
+            },
+        };
+    }
+}
+
+/// Rows of a sparse table which are overlapped in one array.<br>
+/// The entry for (state, symbol) is 'next[base[state] + symbol]' if 'check' of it is 'state + 1'.
+pub struct DisplacementTable {
+    pub base: TableArray,
+    pub check: TableArray,
+    pub next: TableArray,
+}
+
+impl DisplacementTable {
+    pub fn get(&self, state: usize, symbol_id: usize) -> Option<usize> {
+        let index = self.base.get(state) + symbol_id;
+        if index < self.check.len() && self.check.get(index) == state + 1 {
+            return Some(self.next.get(index));
+        }
+        return None;
+    }
+}
+
+/// An array of the smallest integer type which can hold all elements.
+pub enum TableArray {
+    U8(&'static [u8]),
+    U16(&'static [u16]),
+    U32(&'static [u32]),
+}
+
+impl TableArray {
+    pub fn get(&self, index: usize) -> usize {
+        return match self {
+            TableArray::U8(array) => array[index] as usize,
+            TableArray::U16(array) => array[index] as usize,
+            TableArray::U32(array) => array[index] as usize,
+        };
+    }
+
+    pub fn len(&self) -> usize {
+        return match self {
+            TableArray::U8(array) => array.len(),
+            TableArray::U16(array) => array.len(),
+            TableArray::U32(array) => array.len(),
+        };
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+/// Code of 'ParseTable::Dense'.
+pub(crate) fn dense_table_code(table: &Vec<Vec<Option<Operation>>>) -> String {
+    let mut group_array_str = String::new();
+    for group in table.iter() {
+        let mut array_str = String::new();
+
+        for operation in group.iter() {
+            match operation {
+                Some(operation) => {
+                    let tuple = operation.to_tuple();
+                    array_str += format!("({}, {}), ", tuple.0, tuple.1).as_str();
+                }
+                _ => array_str += "(0, 0), ",
+            }
+        }
+
+        group_array_str += format!("&[{}], ", array_str).as_str();
+    }
+
+    return format!("ParseTable::Dense(&[{}])", group_array_str);
+}
+
+/// Code of 'ParseTable::Compressed'.<br>
+/// Unless 'use_default_reductions', the table behaves exactly like 'ParseTable::Dense'.
+pub(crate) fn compressed_table_code(
+    table: &Vec<Vec<Option<Operation>>>,
+    symbol_is_terminal: &Vec<bool>,
+    use_default_reductions: bool,
+) -> String {
+    let mut action_rows = Vec::<Vec<(usize, usize)>>::new();
+    let mut goto_rows = Vec::<Vec<(usize, usize)>>::new();
+    let mut default_reductions = Vec::<usize>::new();
+
+    for group in table.iter() {
+        let mut action_row = Vec::<(usize, usize)>::new();
+        let mut goto_row = Vec::<(usize, usize)>::new();
+
+        for (symbol_id, operation) in group.iter().enumerate() {
+            let (operation, argument) = match operation {
+                Some(operation) => operation.to_tuple(),
+                _ => continue,
+            };
+            if operation == OPERATION_NONE {
+                continue;
+            }
+
+            if symbol_is_terminal[symbol_id] {
+                action_row.push((symbol_id, argument * 8 + operation));
+            } else {
+                goto_row.push((symbol_id, argument));
+            }
+        }
+
+        // a state which only reduces by one pattern needs no action entries,
+        // unless '#[nonassoc(..)]' makes errors in it
+        let has_error = group
+            .iter()
+            .any(|operation| matches!(operation, Some(Operation::Error)));
+        let first_entry = action_row.first().cloned();
+        let default_reduction = match first_entry {
+            Some((_, entry))
+                if use_default_reductions && entry % 8 == OPERATION_REDUCE && !has_error =>
+            {
+                if action_row.iter().all(|(_, other)| *other == entry) {
+                    action_row.clear();
+                    entry / 8 + 1
+                } else {
+                    0
+                }
+            }
+            _ => 0,
+        };
+
+        action_rows.push(action_row);
+        goto_rows.push(goto_row);
+        default_reductions.push(default_reduction);
+    }
+
+    return format!(
+        "ParseTable::Compressed(CompressedTable {{ action: {}, goto: {}, default_reductions: {} }})",
+        displacement_table_code(&action_rows),
+        displacement_table_code(&goto_rows),
+        table_array_code(&default_reductions)
+    );
+}
+
+fn displacement_table_code(rows: &Vec<Vec<(usize, usize)>>) -> String {
+    let mut base = vec![0; rows.len()];
+    let mut check = Vec::<usize>::new();
+    let mut next = Vec::<usize>::new();
+
+    // place longer rows first, they are harder to fit
+    let mut row_order = (0..rows.len()).collect::<Vec<_>>();
+    row_order.sort_by_key(|state| (usize::MAX - rows[*state].len(), *state));
+
+    for state in row_order {
+        let row = &rows[state];
+        if row.is_empty() {
+            continue;
+        }
+
+        let mut row_base = 0;
+        while !row.iter().all(|(symbol_id, _)| {
+            check
+                .get(row_base + symbol_id)
+                .is_none_or(|entry| *entry == 0)
+        }) {
+            row_base += 1;
+        }
+
+        for (symbol_id, entry) in row.iter() {
+            let index = row_base + symbol_id;
+            if index >= check.len() {
+                check.resize(index + 1, 0);
+                next.resize(index + 1, 0);
+            }
+            check[index] = state + 1;
+            next[index] = *entry;
+        }
+        base[state] = row_base;
+    }
+
+    return format!(
+        "DisplacementTable {{ base: {}, check: {}, next: {} }}",
+        table_array_code(&base),
+        table_array_code(&check),
+        table_array_code(&next)
+    );
+}
+
+fn table_array_code(array: &Vec<usize>) -> String {
+    let max = array.iter().max().cloned().unwrap_or(0);
+    let type_name = if max <= u8::MAX as usize {
+        "U8"
+    } else if max <= u16::MAX as usize {
+        "U16"
+    } else {
+        "U32"
+    };
+
+    let mut array_str = String::new();
+    for element in array.iter() {
+        array_str += format!("{}, ", element).as_str();
+    }
+
+    return format!("TableArray::{}(&[{}])", type_name, array_str);
+}
@@ -0,0 +1,454 @@
+use crate::values::get_symbols;
+use crate::{sorted_rules, BNFRule, BNFRuleKind, BNFSymbol, ParserGenerator};
+use std::collections::{HashMap, HashSet};
+use syn::Error;
+
+/// Name of the module which has typed ASTs, generated by '#[typed_ast = true]'.
+pub const TYPED_AST_MODULE_NAME: &str = "typed_ast";
+
+// types are qualified in all generated code,
+// because rules like 'option' or 'terminal_node' define types of the same names
+pub(crate) const OPTION_PATH: &str = "::std::option::Option";
+pub(crate) const VEC_PATH: &str = "::std::vec::Vec";
+pub(crate) const RESULT_PATH: &str = "::std::result::Result";
+const BOX_PATH: &str = "::std::boxed::Box";
+/// Imported by the generated code, because 'TerminalNode' of 'use typed_ast::*' is ambiguous with it.
+pub(crate) const TERMINAL_NODE_PATH: &str = "super::__TerminalNode";
+
+impl ParserGenerator {
+    /// Code of the 'typed_ast' module, which has a struct or an enum for each rule,
+    /// and '__reduce' to build them.
+    pub(crate) fn typed_ast_code(&self) -> Result<String, Error> {
+        return TypedAst::new(self).code();
+    }
+
+    /// Code of 'parse_{entry}_typed', which parses the source into a typed AST.
+    pub(crate) fn typed_entry_code(&self, entry: &String, start_state: usize) -> String {
+        let typed_ast = TypedAst::new(self);
+        let value_type = format!(
+            "{}::{}",
+            TYPED_AST_MODULE_NAME,
+            typed_ast.value_type(&self.rule_map[entry])
+        );
+        return self.value_entry_code(
+            format!("parse_{}_typed", entry).as_str(),
+            TYPED_AST_MODULE_NAME,
+            value_type.as_str(),
+            entry,
+            start_state,
+        );
+    }
+}
+
+/// Names of types in the 'typed_ast' module.<br>
+/// Unnamed rules are tuples, 'Option' for '[ ]' and 'Vec' for '{ }'.
+/// A group with some patterns, or with labelled symbols, has its own type
+/// named after the label, e.g. 'BinopOp' for 'binop ::= .. op:("+" | "-") ..',
+/// or 'Group{number}' counted in the named rule, e.g. 'BinopGroup1'.
+struct TypedAst<'generator> {
+    generator: &'generator ParserGenerator,
+    /// Type names of named rules, and groups which have their own types.
+    type_names: HashMap<String, String>,
+}
+
+impl<'generator> TypedAst<'generator> {
+    fn new(generator: &'generator ParserGenerator) -> Self {
+        let mut typed_ast = Self {
+            generator,
+            type_names: HashMap::new(),
+        };
+        let mut used_names = HashSet::<String>::new();
+
+        let named_rules = sorted_rules(&generator.rule_map)
+            .into_iter()
+            .filter(|rule| rule.kind == BNFRuleKind::Named)
+            .collect::<Vec<_>>();
+        for rule in named_rules.iter() {
+            let type_name = unique_name(
+                to_type_name(&rule.non_terminal_symbol_name),
+                &mut used_names,
+            );
+            typed_ast
+                .type_names
+                .insert(rule.non_terminal_symbol_name.clone(), type_name);
+        }
+        for rule in named_rules.iter() {
+            let parent = typed_ast.type_names[&rule.non_terminal_symbol_name].clone();
+            typed_ast.name_groups(rule, &parent, &mut 0, &mut used_names);
+        }
+
+        return typed_ast;
+    }
+
+    fn name_groups(
+        &mut self,
+        rule: &BNFRule,
+        parent: &str,
+        group_count: &mut usize,
+        used_names: &mut HashSet<String>,
+    ) {
+        for (pattern, pattern_info) in rule.or_patterns.iter().zip(rule.pattern_infos.iter()) {
+            for (symbol, label) in pattern.iter().zip(pattern_info.labels.iter()) {
+                self.name_group(symbol, label, parent, group_count, used_names);
+            }
+        }
+    }
+
+    /// The label of '[ ]' or '{ }' names the group in it.
+    fn name_group(
+        &mut self,
+        symbol: &BNFSymbol,
+        label: &Option<String>,
+        parent: &str,
+        group_count: &mut usize,
+        used_names: &mut HashSet<String>,
+    ) {
+        let generator = self.generator;
+        let rule = match symbol {
+            BNFSymbol::NonTerminalSymbolName(name) => &generator.rule_map[name],
+            _ => return,
+        };
+
+        match rule.kind {
+            BNFRuleKind::Optional => {
+                self.name_group(
+                    &rule.or_patterns[0][0],
+                    label,
+                    parent,
+                    group_count,
+                    used_names,
+                );
+            }
+            BNFRuleKind::Repetition => {
+                self.name_group(
+                    &rule.or_patterns[1][0],
+                    label,
+                    parent,
+                    group_count,
+                    used_names,
+                );
+            }
+            BNFRuleKind::Group => {
+                // a group which is shared by some rules is named by the first one
+                if self.type_names.contains_key(&rule.non_terminal_symbol_name) {
+                    return;
+                }
+                if has_own_type(rule) {
+                    let type_name = match label {
+                        Some(label) => format!("{}{}", parent, to_type_name(label)),
+                        _ => {
+                            *group_count += 1;
+                            format!("{}Group{}", parent, group_count)
+                        }
+                    };
+                    self.type_names.insert(
+                        rule.non_terminal_symbol_name.clone(),
+                        unique_name(type_name, used_names),
+                    );
+                }
+                self.name_groups(rule, parent, group_count, used_names);
+            }
+            BNFRuleKind::Named | BNFRuleKind::Start => {}
+        }
+    }
+
+    fn code(&self) -> Result<String, Error> {
+        let mut code = format!("pub mod {} {{", TYPED_AST_MODULE_NAME);
+        code += "use super::*;";
+
+        for rule in sorted_rules(&self.generator.rule_map) {
+            let type_name = match self.type_names.get(&rule.non_terminal_symbol_name) {
+                Some(type_name) => type_name,
+                _ => continue,
+            };
+
+            if rule.or_patterns.len() == 1 {
+                let fields = self.fields_code(rule, 0, "pub ")?;
+                let semicolon = if fields.starts_with('(') { ";" } else { "" };
+                code += format!(
+                    "#[derive(Debug, Clone, Hash, Eq, PartialEq)] pub struct {}{}{}",
+                    type_name, fields, semicolon
+                )
+                .as_str();
+            } else {
+                let mut variants = String::new();
+                for (index, name) in self.variant_names(rule).iter().enumerate() {
+                    variants +=
+                        format!("{}{}, ", name, self.fields_code(rule, index, "")?).as_str();
+                }
+                code += format!(
+                    "#[derive(Debug, Clone, Hash, Eq, PartialEq)] pub enum {} {{ {} }}",
+                    type_name, variants
+                )
+                .as_str();
+            }
+        }
+
+        code += self
+            .generator
+            .reduce_code(&|rule| self.value_type(rule), &|rule, index, pattern| {
+                return self.value_code(rule, index, pattern);
+            })?
+            .as_str();
+
+        code += "}";
+        return Ok(code);
+    }
+
+    /// Code which makes the typed AST of the pattern in '__reduce'.
+    fn value_code(
+        &self,
+        rule: &BNFRule,
+        index: usize,
+        pattern: &Vec<BNFSymbol>,
+    ) -> Result<String, Error> {
+        let mut values = Vec::<String>::new();
+        for (i, symbol) in pattern.iter().enumerate() {
+            values.push(match symbol {
+                BNFSymbol::NonTerminalSymbolName(name)
+                    if self.generator.rule_map[name].kind == BNFRuleKind::Named =>
+                {
+                    format!("{}::new(__v{})", BOX_PATH, i)
+                }
+                _ => format!("__v{}", i),
+            });
+        }
+
+        if let Some(type_name) = self.type_names.get(&rule.non_terminal_symbol_name) {
+            let arguments = match self.field_names(rule, index)? {
+                Some(names) => {
+                    let fields = names
+                        .iter()
+                        .zip(values.iter())
+                        .map(|(name, value)| format!("{}: {}", name, value))
+                        .collect::<Vec<_>>();
+                    format!("{{ {} }}", fields.join(", "))
+                }
+                _ => format!("({})", values.join(", ")),
+            };
+            return Ok(if rule.or_patterns.len() > 1 {
+                format!(
+                    "{}::{}{}",
+                    type_name,
+                    self.variant_names(rule)[index],
+                    arguments
+                )
+            } else {
+                format!("{}{}", type_name, arguments)
+            });
+        }
+
+        return Ok(match rule.kind {
+            BNFRuleKind::Group if values.len() == 1 => values[0].clone(),
+            BNFRuleKind::Group => format!("({})", values.join(", ")),
+            BNFRuleKind::Optional if values.is_empty() => format!("{}::None", OPTION_PATH),
+            BNFRuleKind::Optional => format!("{}::Some(__v0)", OPTION_PATH),
+            BNFRuleKind::Repetition if values.is_empty() => format!("{}::new()", VEC_PATH),
+            _ => {
+                let element = match self.unboxed_element(&pattern[0]) {
+                    Some(_) => "*__v0",
+                    _ => "__v0",
+                };
+                format!(
+                    "{{ let mut values = __v1; values.insert(0, {}); values }}",
+                    element
+                )
+            }
+        });
+    }
+
+    /// Type of values of the rule in '__Value'.
+    fn value_type(&self, rule: &BNFRule) -> String {
+        if let Some(type_name) = self.type_names.get(&rule.non_terminal_symbol_name) {
+            return type_name.clone();
+        }
+
+        return match rule.kind {
+            BNFRuleKind::Group => match get_symbols(&rule.or_patterns[0]).as_slice() {
+                [symbol] => self.field_type(symbol),
+                symbols => {
+                    let types = symbols
+                        .iter()
+                        .map(|symbol| self.field_type(symbol))
+                        .collect::<Vec<_>>();
+                    format!("({})", types.join(", "))
+                }
+            },
+            BNFRuleKind::Optional => {
+                format!(
+                    "{}<{}>",
+                    OPTION_PATH,
+                    self.field_type(&rule.or_patterns[0][0])
+                )
+            }
+            BNFRuleKind::Repetition => {
+                let symbol = &rule.or_patterns[1][0];
+                let element = match self.unboxed_element(symbol) {
+                    Some(element) => self.value_type(element),
+                    _ => self.field_type(symbol),
+                };
+                format!("{}<{}>", VEC_PATH, element)
+            }
+            BNFRuleKind::Named | BNFRuleKind::Start => String::new(),
+        };
+    }
+
+    /// Fields of the pattern, '{ label: T, .. }' if some symbols are labelled, otherwise '(T, ..)'.
+    fn fields_code(&self, rule: &BNFRule, index: usize, visibility: &str) -> Result<String, Error> {
+        let types = get_symbols(&rule.or_patterns[index])
+            .into_iter()
+            .map(|symbol| self.field_type(symbol))
+            .collect::<Vec<_>>();
+
+        return Ok(match self.field_names(rule, index)? {
+            Some(names) => {
+                let mut fields = String::new();
+                for (name, field_type) in names.iter().zip(types.iter()) {
+                    fields += format!("{}{}: {}, ", visibility, name, field_type).as_str();
+                }
+                format!("{{ {} }}", fields)
+            }
+            _ => {
+                let mut fields = String::new();
+                for field_type in types.iter() {
+                    fields += format!("{}{}, ", visibility, field_type).as_str();
+                }
+                format!("({})", fields)
+            }
+        });
+    }
+
+    /// Names of fields if some symbols of the pattern are labelled,
+    /// unlabelled symbols are named by their positions, e.g. '_0'.
+    fn field_names(&self, rule: &BNFRule, index: usize) -> Result<Option<Vec<String>>, Error> {
+        let pattern_info = &rule.pattern_infos[index];
+        if pattern_info.labels.iter().all(|label| label.is_none()) {
+            return Ok(None);
+        }
+
+        let mut names = Vec::<String>::new();
+        let symbols = rule.or_patterns[index]
+            .iter()
+            .zip(pattern_info.labels.iter())
+            .zip(pattern_info.symbol_spans.iter())
+            .filter(|((symbol, _), _)| !matches!(symbol, BNFSymbol::Null | BNFSymbol::EOF));
+        for ((_, label), span) in symbols {
+            let name = match label {
+                Some(label) => to_field_name(label),
+                _ => format!("_{}", names.len()),
+            };
+            if names.contains(&name) {
+                return Err(Error::new(
+                    *span,
+                    format!(
+                        "Field '{}' is defined more than once in the typed AST of this pattern.",
+                        name
+                    ),
+                ));
+            }
+            names.push(name);
+        }
+        return Ok(Some(names));
+    }
+
+    /// Named rules are boxed, because they may be recursive.
+    fn field_type(&self, symbol: &BNFSymbol) -> String {
+        return match symbol {
+            BNFSymbol::NonTerminalSymbolName(name) => {
+                let rule = &self.generator.rule_map[name];
+                match rule.kind {
+                    BNFRuleKind::Named => format!("{}<{}>", BOX_PATH, self.value_type(rule)),
+                    _ => self.value_type(rule),
+                }
+            }
+            _ => TERMINAL_NODE_PATH.to_string(),
+        };
+    }
+
+    /// The named rule if the content of '{ }' is only the rule, which is not boxed in 'Vec'.
+    fn unboxed_element(&self, symbol: &BNFSymbol) -> Option<&'generator BNFRule> {
+        let rule_map = &self.generator.rule_map;
+        let content = match symbol {
+            BNFSymbol::NonTerminalSymbolName(name) => &rule_map[name],
+            _ => return None,
+        };
+        if content.or_patterns.len() != 1 {
+            return None;
+        }
+        return match get_symbols(&content.or_patterns[0]).as_slice() {
+            [BNFSymbol::NonTerminalSymbolName(name)]
+                if rule_map[name].kind == BNFRuleKind::Named =>
+            {
+                Some(&rule_map[name])
+            }
+            _ => None,
+        };
+    }
+
+    /// A pattern of one named rule is named after it, otherwise 'Pattern{number}'.
+    fn variant_names(&self, rule: &BNFRule) -> Vec<String> {
+        let mut names = Vec::<String>::new();
+        let mut name_set = HashSet::<String>::new();
+        for (index, pattern) in rule.or_patterns.iter().enumerate() {
+            let name = match get_symbols(pattern).as_slice() {
+                [BNFSymbol::NonTerminalSymbolName(name)]
+                    if self.generator.rule_map[name].kind == BNFRuleKind::Named =>
+                {
+                    self.type_names[name].clone()
+                }
+                _ => format!("Pattern{}", index + 1),
+            };
+            let name = if name_set.contains(&name) {
+                format!("Pattern{}", index + 1)
+            } else {
+                name
+            };
+            name_set.insert(name.clone());
+            names.push(name);
+        }
+        return names;
+    }
+}
+
+/// A group has its own type if it has some patterns, or labelled symbols.
+fn has_own_type(rule: &BNFRule) -> bool {
+    if rule.or_patterns.len() > 1 {
+        return true;
+    }
+    let pattern_info = &rule.pattern_infos[0];
+    return get_symbols(&rule.or_patterns[0]).len() > 1
+        && pattern_info.labels.iter().any(|label| label.is_some());
+}
+
+/// Adds a number to the name if it is already used, e.g. 'BinopOp2' if a rule 'binop_op' is defined.
+fn unique_name(name: String, used_names: &mut HashSet<String>) -> String {
+    let mut unique_name = name.clone();
+    let mut number = 2;
+    while used_names.contains(&unique_name) {
+        unique_name = format!("{}{}", name, number);
+        number += 1;
+    }
+    used_names.insert(unique_name.clone());
+    return unique_name;
+}
+
+/// e.g. 'expr_list' to 'ExprList'
+fn to_type_name(rule_name: &str) -> String {
+    let mut type_name = String::new();
+    for word in rule_name.split('_') {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            type_name.extend(first.to_uppercase());
+            type_name.extend(chars);
+        }
+    }
+    return type_name;
+}
+
+/// Labels which are keywords are raw identifiers, e.g. 'r#type'.
+fn to_field_name(label: &str) -> String {
+    return match syn::parse_str::<syn::Ident>(label) {
+        Ok(_) => label.to_string(),
+        _ => format!("r#{}", label),
+    };
+}
@@ -0,0 +1,136 @@
+use crate::typed_ast::{RESULT_PATH, VEC_PATH};
+use crate::{sorted_rules, BNFRule, BNFRuleKind, BNFSymbol, ParserGenerator, ScanMode};
+use syn::Error;
+
+/// Makes the code of the value of a pattern (rule, index in the rule, symbols), see 'reduce_code'.
+pub(crate) type PatternValue<'a> =
+    dyn Fn(&BNFRule, usize, &Vec<BNFSymbol>) -> Result<String, Error> + 'a;
+
+impl ParserGenerator {
+    /// Code of '__Value' which has a variant 'S{symbol id}' of 'value_type' for each rule,
+    /// and '__reduce' for '__parse_values'.<br>
+    /// 'value' makes the code of the value of the pattern (index in the rule, symbols),
+    /// from variables '__v0', '__v1', .. of its symbols.
+    pub(crate) fn reduce_code(
+        &self,
+        value_type: &dyn Fn(&BNFRule) -> String,
+        value: &PatternValue<'_>,
+    ) -> Result<String, Error> {
+        let mut value_variants = String::new();
+        let mut reduce_arms = String::new();
+
+        for rule in sorted_rules(&self.rule_map) {
+            if rule.kind == BNFRuleKind::Start {
+                continue;
+            }
+
+            let symbol_id = self.symbol_id_map
+                [&BNFSymbol::NonTerminalSymbolName(rule.non_terminal_symbol_name.clone())];
+            let rule_value_type = value_type(rule);
+            value_variants += format!("S{}({}), ", symbol_id, rule_value_type).as_str();
+
+            let pattern_ids = &self.rule_pattern_map[&rule.non_terminal_symbol_name];
+            for (index, pattern_id) in pattern_ids.iter().enumerate() {
+                let pattern = &self.single_pattern_rules[*pattern_id].pattern;
+
+                let mut arm = String::new();
+                for (i, symbol) in pattern.iter().enumerate() {
+                    arm += format!("let __v{} = {};", i, self.extract_value_code(symbol)).as_str();
+                }
+                arm += format!(
+                    "let __value: {} = {};",
+                    rule_value_type,
+                    value(rule, index, pattern)?
+                )
+                .as_str();
+
+                reduce_arms += format!(
+                    "{} => {{ {} __Value::S{}(__value) }}",
+                    pattern_id, arm, symbol_id
+                )
+                .as_str();
+            }
+        }
+
+        let mut code = format!("pub(super) enum __Value {{ {} }}", value_variants);
+        code += format!(
+            "pub(super) fn __reduce(pattern_id: usize, children: {}<super::SymbolValue<__Value>>) -> __Value {{",
+            VEC_PATH
+        )
+        .as_str();
+        code += "let mut children = children.into_iter();";
+        code += format!(
+            "return match pattern_id {{ {} _ => unreachable!() }};",
+            reduce_arms
+        )
+        .as_str();
+        code += "}";
+        return Ok(code);
+    }
+
+    /// Code of a function which parses the source from 'entry' into the value of '__reduce' in 'module'.
+    pub(crate) fn value_entry_code(
+        &self,
+        function_name: &str,
+        module: &str,
+        value_type: &str,
+        entry: &str,
+        start_state: usize,
+    ) -> String {
+        let symbol_id = self.symbol_id_map[&BNFSymbol::NonTerminalSymbolName(entry.to_string())];
+        return format!(
+            "pub fn {function_name}(source: &str) -> {result}<{value_type}, ParseError> {{
+                return match __bnf_rules_parse_values(source, {start_state}, &{module}::__reduce)? {{
+                    {module}::__Value::S{symbol_id}(value) => {result}::Ok(value),
+                    _ => unreachable!(),
+                }};
+            }}",
+            function_name = function_name,
+            value_type = value_type,
+            module = module,
+            start_state = start_state,
+            symbol_id = symbol_id,
+            result = RESULT_PATH,
+        );
+    }
+
+    /// Code of '__bnf_rules_parse_values', values are computed at each reduction, instead of building nodes.
+    pub(crate) fn parse_values_code(&self) -> String {
+        let mut code = format!(
+            "fn __bnf_rules_parse_values<V>(source: &str, start_state: usize, reduce: &dyn Fn(usize, {}<SymbolValue<V>>) -> V) -> {}<V, ParseError> {{",
+            VEC_PATH, RESULT_PATH
+        );
+        code += "let tables = __bnf_rules_tables();";
+        code += match self.settings.scan_mode {
+            ScanMode::Batch => {
+                "let tokens = __bnf_rules_lexer().scan(source);
+                return __parse_values(tokens, tables, start_state, reduce);"
+            }
+            ScanMode::OnDemand => {
+                "let scanner = __bnf_rules_lexer().scan_on_demand(source);
+                return __parse_values_on_demand(scanner, tables, start_state, reduce);"
+            }
+        };
+        code += "}";
+        return code;
+    }
+
+    /// Code which takes the value of the next symbol from 'children' in '__reduce'.
+    fn extract_value_code(&self, symbol: &BNFSymbol) -> String {
+        return match symbol {
+            BNFSymbol::NonTerminalSymbolName(_) => format!(
+                "match children.next().unwrap().into_value() {{ __Value::S{}(value) => value, _ => unreachable!() }}",
+                self.symbol_id_map[symbol]
+            ),
+            _ => "children.next().unwrap().into_terminal()".to_string(),
+        };
+    }
+}
+
+/// Symbols of the pattern without 'Null'.
+pub(crate) fn get_symbols(pattern: &Vec<BNFSymbol>) -> Vec<&BNFSymbol> {
+    return pattern
+        .iter()
+        .filter(|symbol| !matches!(symbol, BNFSymbol::Null | BNFSymbol::EOF))
+        .collect();
+}
@@ -51,7 +51,10 @@ mod tests {
                                                 text_length: 2,
                                                 line: 1,
                                                 column: 1,
+                                                end_line: 1,
+                                                end_column: 3,
                                             },
+                                            label: None,
                                         },]
                                         .to_vec(),
                                         position: TokenPosition {
@@ -59,7 +62,10 @@ mod tests {
                                             text_length: 2,
                                             line: 1,
                                             column: 1,
+                                            end_line: 1,
+                                            end_column: 3,
                                         },
+                                        label: None,
                                     },]
                                     .to_vec(),
                                     position: TokenPosition {
@@ -67,7 +73,10 @@ mod tests {
                                         text_length: 2,
                                         line: 1,
                                         column: 1,
+                                        end_line: 1,
+                                        end_column: 3,
                                     },
+                                    label: None,
                                 },]
                                 .to_vec(),
                                 position: TokenPosition {
@@ -75,7 +84,10 @@ mod tests {
                                     text_length: 2,
                                     line: 1,
                                     column: 1,
+                                    end_line: 1,
+                                    end_column: 3,
                                 },
+                                label: None,
                             },
                             Terminal {
                                 internal_symbol_id: None,
@@ -85,7 +97,10 @@ mod tests {
                                     text_length: 1,
                                     line: 1,
                                     column: 4,
+                                    end_line: 1,
+                                    end_column: 5,
                                 },
+                                label: None,
                             },
                             NonTerminal {
                                 internal_symbol_id: None,
@@ -102,7 +117,10 @@ mod tests {
                                                 text_length: 1,
                                                 line: 1,
                                                 column: 6,
+                                                end_line: 1,
+                                                end_column: 7,
                                             },
+                                            label: None,
                                         },
                                         NonTerminal {
                                             internal_symbol_id: None,
@@ -125,7 +143,10 @@ mod tests {
                                                                     text_length: 3,
                                                                     line: 1,
                                                                     column: 7,
+                                                                    end_line: 1,
+                                                                    end_column: 10,
                                                                 },
+                                                                label: None,
                                                             },]
                                                             .to_vec(),
                                                             position: TokenPosition {
@@ -133,7 +154,10 @@ mod tests {
                                                                 text_length: 3,
                                                                 line: 1,
                                                                 column: 7,
+                                                                end_line: 1,
+                                                                end_column: 10,
                                                             },
+                                                            label: None,
                                                         },]
                                                         .to_vec(),
                                                         position: TokenPosition {
@@ -141,7 +165,10 @@ mod tests {
                                                             text_length: 3,
                                                             line: 1,
                                                             column: 7,
+                                                            end_line: 1,
+                                                            end_column: 10,
                                                         },
+                                                        label: None,
                                                     },]
                                                     .to_vec(),
                                                     position: TokenPosition {
@@ -149,7 +176,10 @@ mod tests {
                                                         text_length: 3,
                                                         line: 1,
                                                         column: 7,
+                                                        end_line: 1,
+                                                        end_column: 10,
                                                     },
+                                                    label: None,
                                                 },
                                                 Terminal {
                                                     internal_symbol_id: None,
@@ -159,7 +189,10 @@ mod tests {
                                                         text_length: 1,
                                                         line: 1,
                                                         column: 11,
+                                                        end_line: 1,
+                                                        end_column: 12,
                                                     },
+                                                    label: None,
                                                 },
                                                 NonTerminal {
                                                     internal_symbol_id: None,
@@ -173,7 +206,10 @@ mod tests {
                                                                 text_length: 1,
                                                                 line: 1,
                                                                 column: 13,
+                                                                end_line: 1,
+                                                                end_column: 14,
                                                             },
+                                                            label: None,
                                                         },
                                                         NonTerminal {
                                                             internal_symbol_id: None,
@@ -189,7 +225,10 @@ mod tests {
                                                                         text_length: 2,
                                                                         line: 1,
                                                                         column: 14,
+                                                                        end_line: 1,
+                                                                        end_column: 16,
                                                                     },
+                                                                    label: None,
                                                                 },]
                                                                 .to_vec(),
                                                                 position: TokenPosition {
@@ -197,7 +236,10 @@ mod tests {
                                                                     text_length: 2,
                                                                     line: 1,
                                                                     column: 14,
+                                                                    end_line: 1,
+                                                                    end_column: 16,
                                                                 },
+                                                                label: None,
                                                             },]
                                                             .to_vec(),
                                                             position: TokenPosition {
@@ -205,7 +247,10 @@ mod tests {
                                                                 text_length: 2,
                                                                 line: 1,
                                                                 column: 14,
+                                                                end_line: 1,
+                                                                end_column: 16,
                                                             },
+                                                            label: None,
                                                         },
                                                     ]
                                                     .to_vec(),
@@ -214,7 +259,10 @@ mod tests {
                                                         text_length: 3,
                                                         line: 1,
                                                         column: 13,
+                                                        end_line: 1,
+                                                        end_column: 16,
                                                     },
+                                                    label: None,
                                                 },
                                             ]
                                             .to_vec(),
@@ -223,7 +271,10 @@ mod tests {
                                                 text_length: 9,
                                                 line: 1,
                                                 column: 7,
+                                                end_line: 1,
+                                                end_column: 16,
                                             },
+                                            label: None,
                                         },
                                         Terminal {
                                             internal_symbol_id: None,
@@ -233,7 +284,10 @@ mod tests {
                                                 text_length: 1,
                                                 line: 1,
                                                 column: 16,
+                                                end_line: 1,
+                                                end_column: 17,
                                             },
+                                            label: None,
                                         },
                                     ]
                                     .to_vec(),
@@ -242,7 +296,10 @@ mod tests {
                                         text_length: 11,
                                         line: 1,
                                         column: 6,
+                                        end_line: 1,
+                                        end_column: 17,
                                     },
+                                    label: None,
                                 },]
                                 .to_vec(),
                                 position: TokenPosition {
@@ -250,7 +307,10 @@ mod tests {
                                     text_length: 11,
                                     line: 1,
                                     column: 6,
+                                    end_line: 1,
+                                    end_column: 17,
                                 },
+                                label: None,
                             },
                         ]
                         .to_vec(),
@@ -259,7 +319,10 @@ mod tests {
                             text_length: 16,
                             line: 1,
                             column: 1,
+                            end_line: 1,
+                            end_column: 17,
                         },
+                        label: None,
                     },]
                     .to_vec(),
                     position: TokenPosition {
@@ -267,7 +330,10 @@ mod tests {
                         text_length: 16,
                         line: 1,
                         column: 1,
+                        end_line: 1,
+                        end_column: 17,
                     },
+                    label: None,
                 }
             );
         }
@@ -289,5 +355,1506 @@ mod tests {
             let node = parse_source(r#""Hello, world!""#).unwrap();
             dbg!(node);
         }
+
+        #[test]
+        fn test_lexer_reuse() {
+            assert!(std::ptr::eq(__bnf_rules_lexer(), __bnf_rules_lexer()));
+
+            let threads = (0..4)
+                .map(|i| std::thread::spawn(move || parse_source(&format!("\"{}\"", i)).is_ok()))
+                .collect::<Vec<_>>();
+            for thread in threads {
+                assert!(thread.join().unwrap());
+            }
+        }
+    }
+
+    mod test_precedence {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[nonassoc("==")]
+            #[left("+", "-")]
+            #[left("*")]
+            #[right("^")]
+            #[right(UMINUS)]
+
+            source   ::= expr
+            expr     ::= expr "+" expr
+                       | expr "-" expr
+                       | expr "*" expr
+                       | expr "^" expr
+                       | expr "==" expr
+                       | "-" expr %prec UMINUS
+                       | number
+            number   ::= r"\d+"
+        );
+
+        fn to_s_expression(node: &ASTNode) -> String {
+            return match node {
+                NonTerminal {
+                    symbol_name,
+                    children,
+                    ..
+                } => {
+                    if symbol_name == "number" || symbol_name == "source" {
+                        return to_s_expression(&children[0]);
+                    }
+                    let children = children.iter().map(to_s_expression).collect::<Vec<_>>();
+                    match children.len() {
+                        3 => format!("({} {} {})", children[1], children[0], children[2]),
+                        2 => format!("({} {})", children[0], children[1]),
+                        _ => children[0].clone(),
+                    }
+                }
+                Terminal { text, .. } => text.clone(),
+                ASTNode::Error { .. } => "error".to_string(),
+            };
+        }
+
+        #[test]
+        fn test() {
+            let node = parse_source("1 + 2 * 3 - 4").unwrap();
+            assert_eq!(to_s_expression(&node), "(- (+ 1 (* 2 3)) 4)");
+
+            let node = parse_source("2 ^ 3 ^ 4 * -5").unwrap();
+            assert_eq!(to_s_expression(&node), "(* (^ 2 (^ 3 4)) (- 5))");
+
+            let node = parse_source("-1 + 2 == 3").unwrap();
+            assert_eq!(to_s_expression(&node), "(== (+ (- 1) 2) 3)");
+
+            assert!(parse_source("1 == 2 == 3").is_err());
+        }
+    }
+
+    mod test_entry {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[entry(expr, statement)]
+
+            statement ::= "let" ident "=" expr ";"
+            expr      ::= ident { "+" ident }
+            ident     ::= r"[a-z]"
+        );
+
+        #[test]
+        fn test() {
+            let node = parse_expr("a + b").unwrap();
+            assert!(matches!(node, NonTerminal { symbol_name, .. } if symbol_name == "expr"));
+
+            let node = parse_statement("let a = b + c;").unwrap();
+            assert!(matches!(node, NonTerminal { symbol_name, .. } if symbol_name == "statement"));
+
+            assert!(parse_expr("let a = b;").is_err());
+            assert!(parse_statement("a + b").is_err());
+        }
+    }
+
+    mod test_skip {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[skip_default = false]
+            #[skip(r"[ \t\r\n]+", r"//[^\n]*", r"/\*([^*]|\*[^/])*\*/")]
+
+            source   ::= { number }
+            number   ::= r"\d+"
+        );
+
+        #[test]
+        fn test() {
+            let node = parse_source("1\t2 // comment\n/* multi\nline */ 3\r\n4").unwrap();
+            match node {
+                NonTerminal { children, .. } => assert_eq!(children.len(), 4),
+                _ => unreachable!(),
+            }
+
+            // '　' is skipped only by default
+            assert!(parse_source("1　2").is_err());
+        }
+    }
+
+    mod test_lexer_priority {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source   ::= { item } "end"
+            item     ::= word | number
+
+            #[priority = 1]
+            #[reserved("end")]
+            word     ::= r"\w+"
+            #[priority = 2]
+            number   ::= r"\d+"
+        );
+
+        fn item_names(node: &ASTNode) -> Vec<String> {
+            return match node {
+                NonTerminal {
+                    symbol_name,
+                    children,
+                    ..
+                } => {
+                    if symbol_name == "word" || symbol_name == "number" {
+                        return vec![symbol_name.clone()];
+                    }
+                    children.iter().flat_map(item_names).collect()
+                }
+                Terminal { .. } | ASTNode::Error { .. } => vec![],
+            };
+        }
+
+        #[test]
+        fn test() {
+            let node = parse_source("abc 12 end").unwrap();
+            assert_eq!(item_names(&node), vec!["word", "number"]);
+
+            assert!(parse_source("abc 12").is_err());
+
+            let (errors, _) = super::generate(r#"source ::= "a" #[priority = 1] "b""#);
+            assert_eq!(errors, vec!["Attributes must be placed before a rule."]);
+        }
+    }
+
+    mod test_dfa_scanner {
+        use crate::bnf_rules_parser::lexer::{Lexer, TerminalSymbol};
+
+        fn digits(input: &str) -> usize {
+            return input.chars().take_while(|c| c.is_ascii_digit()).count();
+        }
+
+        fn lexer() -> Lexer {
+            return Lexer::new(vec![
+                TerminalSymbol::new_from_string("if", 1),
+                TerminalSymbol::new_from_string("==", 2),
+                TerminalSymbol::new_from_string("=", 3),
+                TerminalSymbol::new_from_regex(r"\w+\b", 4).with_reserved(&["else"]),
+                TerminalSymbol::new_from_regex("else|elsewhere", 5),
+                TerminalSymbol::new_from_tokenizer_fn(digits, 6),
+            ]);
+        }
+
+        fn scan(lexer: &Lexer, source: &str) -> Vec<(u32, String)> {
+            return lexer
+                .scan(source)
+                .unwrap()
+                .iter()
+                .map(|token| (token.symbol_id, token.text.to_string()))
+                .collect();
+        }
+
+        #[test]
+        fn test() {
+            let source = "if iffy == 12 else elsewhere = 3 日本語 x";
+            let tokens = scan(&lexer(), source);
+            assert_eq!(tokens, scan(&lexer().without_dfa(), source));
+
+            let ids = tokens.iter().map(|token| token.0).collect::<Vec<_>>();
+            assert_eq!(ids, vec![1, 4, 2, 4, 5, 4, 3, 4, 4, 4, 0]);
+
+            assert!(lexer().scan("if ?").is_err());
+        }
+
+        #[test]
+        fn test_leftmost_first() {
+            let lexer = || {
+                return Lexer::new(vec![
+                    TerminalSymbol::new_from_regex(r"/\*.*?\*/", 1),
+                    TerminalSymbol::new_from_regex("a|ab", 2),
+                    TerminalSymbol::new_from_string("b", 3),
+                ]);
+            };
+
+            // a lazy quantifier stops at the first "*/", and "a|ab" matches only "a"
+            let source = "/* x */ ab /* y */";
+            let tokens = scan(&lexer(), source);
+            assert_eq!(tokens, scan(&lexer().without_dfa(), source));
+            assert_eq!(
+                tokens,
+                vec![
+                    (1, "/* x */".to_string()),
+                    (2, "a".to_string()),
+                    (3, "b".to_string()),
+                    (1, "/* y */".to_string()),
+                    (0, "".to_string()),
+                ]
+            );
+        }
+    }
+
+    mod test_on_demand_scan {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[scan = on_demand]
+
+            source    ::= { statement }
+            statement ::= "async" "fn" name ";" | "let" name ";"
+            name      ::= r"\w+"
+        );
+
+        // the same grammar with the uncompressed table, which must behave the same
+        mod dense {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[compress_table = false]
+                #[scan = on_demand]
+
+                source    ::= { statement }
+                statement ::= "async" "fn" name ";" | "let" name ";"
+                name      ::= r"\w+"
+            );
+        }
+
+        fn texts(node: &ASTNode) -> Vec<String> {
+            return match node {
+                NonTerminal { children, .. } | ASTNode::Error { children, .. } => {
+                    children.iter().flat_map(texts).collect()
+                }
+                Terminal { text, .. } => vec![text.clone()],
+            };
+        }
+
+        #[test]
+        fn test() {
+            check(parse_source, parse_source_with_errors);
+            check(dense::parse_source, dense::parse_source_with_errors);
+        }
+
+        fn check(
+            parse_source: fn(&str) -> Result<ASTNode, ParseError>,
+            parse_source_with_errors: fn(&str) -> (Option<ASTNode>, Vec<ParseError>),
+        ) {
+            let node = parse_source("async fn async;\nlet fn;").unwrap();
+            assert_eq!(
+                texts(&node),
+                vec!["async", "fn", "async", ";", "let", "fn", ";"]
+            );
+            let position = node.get_position();
+            assert_eq!((position.start_position, position.text_length), (0, 23));
+
+            let error = parse_source("async fn x;\nlet ;").unwrap_err();
+            let position = error.position.unwrap();
+            assert_eq!((position.line, position.column), (2, 5));
+
+            assert!(parse_source("fn x;").is_err());
+
+            // 'fn' is not a name here, but it is a syntax error rather than an error of the lexer
+            let (node, errors) = parse_source_with_errors("async fn ?x;\nlet fn fn;");
+            assert!(node.is_some());
+            let positions = errors
+                .iter()
+                .map(|error| error.position.clone().unwrap())
+                .map(|position| (position.line, position.column))
+                .collect::<Vec<_>>();
+            assert_eq!(positions, vec![(1, 10), (2, 8)]);
+        }
+    }
+
+    mod test_lexer_recovery {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source   ::= expr
+            expr     ::= number { "+" number }
+            number   ::= r"\d+"
+        );
+
+        fn error_positions(errors: &Vec<ParseError>) -> Vec<(usize, usize, usize)> {
+            return errors
+                .iter()
+                .map(|error| {
+                    let position = error.position.clone().unwrap();
+                    (position.line, position.column, position.text_length)
+                })
+                .collect();
+        }
+
+        #[test]
+        fn test() {
+            let (node, errors) = parse_source_with_errors("1 + @@ 2\n+ $ 3");
+            assert_eq!(node.unwrap().get_position().text_length, 14);
+            assert_eq!(error_positions(&errors), vec![(1, 5, 2), (2, 3, 1)]);
+
+            // a syntax error is reported after errors of the lexer, and the parser recovers from it
+            let (node, errors) = parse_source_with_errors("1 @ + + 2");
+            assert!(node.is_some());
+            assert_eq!(error_positions(&errors), vec![(1, 3, 1), (1, 7, 1)]);
+
+            let (node, errors) = parse_source_with_errors("1 + 2");
+            assert!(node.is_some() && errors.is_empty());
+        }
+    }
+
+    mod test_lexer_mode {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source        ::= expr
+            expr          ::= name | string
+            string        ::= quote_open { string_part } quote_close
+            interpolation ::= interp_open expr interp_close
+            name          ::= r"[a-z]+"
+
+            #[push(string)]
+            quote_open    ::= "\""
+            #[mode(string)]
+            string_part   ::= r#"[^"$]+"# | interpolation
+            #[mode(string)]
+            #[pop]
+            quote_close   ::= "\""
+            #[mode(string)]
+            #[push(default)]
+            interp_open   ::= "${"
+            #[pop]
+            interp_close  ::= "}"
+        );
+
+        fn texts(node: &ASTNode) -> Vec<String> {
+            return match node {
+                NonTerminal { children, .. } | ASTNode::Error { children, .. } => {
+                    children.iter().flat_map(texts).collect()
+                }
+                Terminal { text, .. } => vec![text.clone()],
+            };
+        }
+
+        #[test]
+        fn test() {
+            let node = parse_source(r#""a ${ "b ${x} c" } d""#).unwrap();
+            assert_eq!(
+                texts(&node),
+                vec!["\"", "a ", "${", "\"", "b ", "${", "x", "}", " c", "\"", "}", " d", "\""]
+            );
+
+            assert!(parse_source(r#"x"#).is_ok());
+            // 'x' is a string part in the string mode
+            assert!(parse_source(r#""x""#).is_ok());
+            assert!(parse_source(r#""a" b"#).is_err());
+
+            let (errors, _) = super::generate(
+                r#"
+                source ::= a
+                #[push(other)]
+                a      ::= "a"
+                "#,
+            );
+            assert_eq!(errors, vec!["Lexer mode 'other' has no terminal symbols."]);
+
+            let (errors, _) = super::generate(
+                r#"
+                source ::= a b
+                #[pop]
+                a      ::= "a"
+                b      ::= "a"
+                "#,
+            );
+            assert_eq!(
+                errors,
+                vec!["Different lexer mode transition is already declared for this symbol in the mode 'default'."]
+            );
+        }
+    }
+
+    mod test_layout {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[layout = true]
+            #[skip(r"#[^\n]*", r"/\*([^*]|\*[^/])*\*/")]
+
+            source    ::= { statement }
+            statement ::= name ":" number NEWLINE
+                        | name ":" NEWLINE INDENT { statement } DEDENT
+            name      ::= r"[a-z]+"
+            number    ::= r"\d+"
+        );
+
+        const SOURCE: &str = "a: 1\nb:\n    c: 2\n\n    # comment\n    d:\n        e: 3\nf: 4";
+
+        #[test]
+        fn test() {
+            let node = parse_source(SOURCE).unwrap();
+            assert_eq!(node.get_position().text_length, SOURCE.len());
+
+            let tokens = __bnf_rules_lexer().scan(SOURCE).unwrap();
+            let texts = tokens
+                .iter()
+                .map(|token| {
+                    if token.is_eof {
+                        "EOF".to_string()
+                    } else if token.text.is_empty() {
+                        // INDENT, DEDENT, or NEWLINE at the end of the source
+                        format!("{}:{}", token.position.line, token.position.column)
+                    } else {
+                        token.text.to_string()
+                    }
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                texts,
+                vec![
+                    "a", ":", "1", "\n", "b", ":", "\n", "3:5", "c", ":", "2", "\n", "d", ":",
+                    "\n", "7:9", "e", ":", "3", "\n", "8:1", "8:1", "f", ":", "4", "8:5", "EOF"
+                ]
+            );
+
+            // on demand scanning makes the same tokens
+            let mut scanner = __bnf_rules_lexer().scan_on_demand(SOURCE);
+            for token in tokens.iter() {
+                let other = scanner.next_token(&|_| true).unwrap();
+                assert_eq!(
+                    (other.symbol_id, other.text, other.position.clone()),
+                    (token.symbol_id, token.text, token.position.clone())
+                );
+            }
+
+            let error = parse_source("a:\n    b: 1\n  c: 2").unwrap_err();
+            let position = error.position.unwrap();
+            assert_eq!((position.line, position.column), (3, 3));
+        }
+
+        #[test]
+        fn test_indentation() {
+            // 'c' is not the first token on its line, so the indentation is not changed
+            assert!(parse_source("a:\n    b: 1 /* x\n*/ c: 2").is_ok());
+
+            // tabs and spaces are never equal
+            assert!(parse_source("a:\n\tb: 1\n\tc: 2").is_ok());
+            let error = parse_source("a:\n\tb: 1\n    c: 2").unwrap_err();
+            let position = error.position.unwrap();
+            assert_eq!((position.line, position.column), (3, 5));
+        }
+    }
+
+    mod test_line_index {
+        use crate::bnf_rules_parser::line_index::{ColumnUnit, LineIndex};
+
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source   ::= { word }
+            word     ::= r"\w+"
+        );
+
+        #[test]
+        fn test() {
+            let source = "ab\r\nあい😀x\ry\n";
+            let index = LineIndex::new(source);
+            assert_eq!(index.line_count(), 4);
+
+            // 'x' is at the byte 14
+            assert_eq!(index.line_column(14, ColumnUnit::Utf8), (2, 11));
+            assert_eq!(index.line_column(14, ColumnUnit::Char), (2, 4));
+            assert_eq!(index.line_column(14, ColumnUnit::Utf16), (2, 5));
+            assert_eq!(index.line_column(16, ColumnUnit::Char), (3, 1));
+            assert_eq!(index.line_column(18, ColumnUnit::Char), (4, 1));
+            // inside of '😀'
+            assert_eq!(index.line_column(11, ColumnUnit::Char), (2, 3));
+
+            for unit in [ColumnUnit::Utf8, ColumnUnit::Char, ColumnUnit::Utf16] {
+                for (offset, _) in source.char_indices() {
+                    let (line, column) = index.line_column(offset, unit);
+                    assert_eq!(index.byte_offset(line, column, unit), Some(offset));
+                }
+            }
+            assert_eq!(index.byte_offset(2, 4, ColumnUnit::Utf16), None);
+            assert_eq!(index.byte_offset(5, 1, ColumnUnit::Char), None);
+
+            // CRLF is one line break for tokens
+            let tokens = __bnf_rules_lexer().scan("ab\r\ncd\r\n  ef").unwrap();
+            let positions = tokens
+                .iter()
+                .map(|token| (token.position.line, token.position.column))
+                .collect::<Vec<_>>();
+            assert_eq!(positions, vec![(1, 1), (2, 1), (3, 3), (3, 4)]);
+        }
+    }
+
+    mod test_span {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source   ::= { item }
+            item     ::= name | "(" { item } ")"
+            name     ::= r"[a-z]+"
+        );
+
+        #[test]
+        fn test() {
+            let source = "x (\n  a\nb ) ()";
+            let node = parse_source(source).unwrap();
+            let items = match &node {
+                NonTerminal { children, .. } => children.clone(),
+                _ => unreachable!(),
+            };
+            let spans = items
+                .iter()
+                .map(|item| {
+                    let position = item.get_position();
+                    (
+                        &source[position.range()],
+                        (position.line, position.column),
+                        (position.end_line, position.end_column),
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                spans,
+                vec![
+                    ("x", (1, 1), (1, 2)),
+                    ("(\n  a\nb )", (1, 3), (3, 4)),
+                    ("()", (3, 5), (3, 7)),
+                ]
+            );
+
+            let position = node.get_position();
+            assert_eq!(position.range(), 0..source.len());
+            assert_eq!((position.line, position.column), (1, 1));
+            assert_eq!((position.end_line, position.end_column), (3, 7));
+        }
+    }
+
+    mod test_expected_tokens {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source   ::= expr
+            expr     ::= term { "+" term }
+            term     ::= "(" expr ")" | "[" expr "]" | number | name
+            name     ::= r"[a-z]\w*"
+
+            #[display = "number"]
+            number   ::= r"\d+"
+        );
+
+        // the same grammar with the uncompressed table, which must behave the same
+        mod dense {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[compress_table = false]
+                source   ::= expr
+                expr     ::= term { "+" term }
+                term     ::= "(" expr ")" | "[" expr "]" | number | name
+                name     ::= r"[a-z]\w*"
+
+                #[display = "number"]
+                number   ::= r"\d+"
+            );
+        }
+
+        #[test]
+        fn test() {
+            check(parse_source);
+            check(dense::parse_source);
+        }
+
+        fn check(parse_source: fn(&str) -> Result<ASTNode, ParseError>) {
+            let error = parse_source("(1 + 2]").unwrap_err();
+            assert_eq!(error.message, r#"expected one of "+", ")" but found "]""#);
+            assert_eq!(error.expected, vec![r#""+""#, r#"")""#]);
+            assert_eq!(error.found, Some(r#""]""#.to_string()));
+            assert_eq!(error.position.unwrap().column, 7);
+
+            let error = parse_source("1 +").unwrap_err();
+            assert_eq!(
+                error.message,
+                r#"expected one of "(", "[", r"[a-z]\w*", number but found EOF"#
+            );
+        }
+    }
+
+    mod test_syntax_recovery {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[sync(";", "}")]
+
+            source    ::= { statement }
+            statement ::= "let" name "=" expr ";" | "{" { statement } "}"
+            expr      ::= term { "+" term }
+            term      ::= name | number
+            name      ::= r"[a-z]+"
+            number    ::= r"\d+"
+        );
+
+        // the same grammar with the uncompressed table, which must behave the same
+        mod dense {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[compress_table = false]
+                #[sync(";", "}")]
+
+                source    ::= { statement }
+                statement ::= "let" name "=" expr ";" | "{" { statement } "}"
+                expr      ::= term { "+" term }
+                term      ::= name | number
+                name      ::= r"[a-z]+"
+                number    ::= r"\d+"
+            );
+        }
+
+        /// Texts of the node, where error nodes are '<..>' and missing tokens are '<>'.
+        fn texts(node: &ASTNode) -> String {
+            return match node {
+                NonTerminal { children, .. } => {
+                    children.iter().map(texts).collect::<Vec<_>>().join(" ")
+                }
+                Terminal { text, .. } => text.clone(),
+                ASTNode::Error { children, .. } => format!(
+                    "<{}>",
+                    children.iter().map(texts).collect::<Vec<_>>().join(" ")
+                ),
+            };
+        }
+
+        fn recover(
+            parse_source_with_errors: fn(&str) -> (Option<ASTNode>, Vec<ParseError>),
+            source: &str,
+        ) -> (String, Vec<(usize, usize)>) {
+            let (node, errors) = parse_source_with_errors(source);
+            let positions = errors
+                .iter()
+                .map(|error| error.position.clone().unwrap())
+                .map(|position| (position.line, position.column))
+                .collect();
+            return (texts(&node.unwrap()), positions);
+        }
+
+        #[test]
+        fn test() {
+            check(parse_source_with_errors);
+            check(dense::parse_source_with_errors);
+        }
+
+        fn check(parse_source_with_errors: fn(&str) -> (Option<ASTNode>, Vec<ParseError>)) {
+            let recover = |source| recover(parse_source_with_errors, source);
+            // insertion
+            assert_eq!(
+                recover("let a = 1\nlet b = 2;"),
+                ("let a = 1 <> let b = 2 ;".to_string(), vec![(2, 1)])
+            );
+            // deletion
+            assert_eq!(
+                recover("let a = 1;; let b = 2;"),
+                ("let a = 1 ; <;> let b = 2 ;".to_string(), vec![(1, 11)])
+            );
+            // substitution
+            assert_eq!(
+                recover("let a = 1 + 2 } let b = 3;"),
+                ("let a = 1 + 2 <}> let b = 3 ;".to_string(), vec![(1, 15)])
+            );
+            // panic mode
+            assert_eq!(
+                recover("let a = = = 1; let b = 2;\n{ let = ; } let c = 3;"),
+                (
+                    "<let a = = = 1 ;> let b = 2 ; { <let = ;> } let c = 3 ;".to_string(),
+                    vec![(1, 9), (2, 7)]
+                )
+            );
+            assert_eq!(
+                recover("let a = 1; = = ; let b = 2;"),
+                ("let a = 1 ; <= = ;> let b = 2 ;".to_string(), vec![(1, 12)])
+            );
+            assert_eq!(
+                recover("let a = 1; { = + = ; let c = 3; }"),
+                (
+                    "let a = 1 ; { <= + = ;> let c = 3 ; }".to_string(),
+                    vec![(1, 14)]
+                )
+            );
+            assert_eq!(
+                recover("= = ; let b = 2;"),
+                ("<= = ;> let b = 2 ;".to_string(), vec![(1, 1)])
+            );
+
+            let (node, errors) = parse_source_with_errors("let a = 1;");
+            assert!(node.is_some() && errors.is_empty());
+        }
+    }
+
+    mod test_error_production {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source    ::= { statement }
+            statement ::= "let" name "=" expr ";" | error ";"
+            expr      ::= name | number
+            name      ::= r"[a-z]+"
+            number    ::= r"\d+"
+        );
+
+        // the same grammar with the uncompressed table, which must behave the same
+        mod dense {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[compress_table = false]
+                source    ::= { statement }
+                statement ::= "let" name "=" expr ";" | error ";"
+                expr      ::= name | number
+                name      ::= r"[a-z]+"
+                number    ::= r"\d+"
+            );
+        }
+
+        /// Texts of statements, where error nodes are '<..>' and missing tokens are '<>'.
+        fn texts(node: &ASTNode) -> String {
+            return match node {
+                NonTerminal {
+                    symbol_name,
+                    children,
+                    ..
+                } => {
+                    let texts = children.iter().map(texts).collect::<Vec<_>>();
+                    if symbol_name == "statement" {
+                        format!("[{}]", texts.join(" "))
+                    } else {
+                        texts.join(" ")
+                    }
+                }
+                Terminal { text, .. } => text.clone(),
+                ASTNode::Error { children, .. } => format!(
+                    "<{}>",
+                    children.iter().map(texts).collect::<Vec<_>>().join(" ")
+                ),
+            };
+        }
+
+        #[test]
+        fn test() {
+            check(parse_source, parse_source_with_errors);
+            check(dense::parse_source, dense::parse_source_with_errors);
+        }
+
+        fn check(
+            parse_source: fn(&str) -> Result<ASTNode, ParseError>,
+            parse_source_with_errors: fn(&str) -> (Option<ASTNode>, Vec<ParseError>),
+        ) {
+            let (node, errors) = parse_source_with_errors("let a = 1; let = 2; let b = 3;");
+            assert_eq!(
+                texts(&node.unwrap()),
+                "[let a = 1 ;] [<let = 2> ;] [let b = 3 ;]"
+            );
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].message, r#"expected r"[a-z]+" but found "=""#);
+
+            // popped symbols are also in the error node
+            let (node, errors) = parse_source_with_errors("let a = 1 let b = 2; let c = 3;");
+            assert_eq!(
+                texts(&node.unwrap()),
+                "[<let a = 1 let b = 2> ;] [let c = 3 ;]"
+            );
+            assert_eq!(errors.len(), 1);
+
+            // a missing ';' at the end is inserted
+            let (node, errors) = parse_source_with_errors("let a = 1; let b = 2");
+            assert_eq!(texts(&node.unwrap()), "[let a = 1 ;] [let b = 2 <>]");
+            assert_eq!(errors.len(), 1);
+
+            // 'error' is not expected
+            let error = parse_source("let a = 1 b").unwrap_err();
+            assert_eq!(error.expected, vec![r#"";""#]);
+        }
+    }
+
+    mod test_typed_ast {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[typed_ast = true]
+
+            source    ::= { statement }
+            statement ::= "let" name [ ":" name ] "=" expr ";" | expr ";"
+            expr      ::= term { ( "+" | "-" ) term }
+            term      ::= "(" expr ")" | name | number
+            name      ::= r"[a-z]+"
+            number    ::= r"\d+"
+        );
+
+        use typed_ast::*;
+
+        fn expr_text(expr: &Expr) -> String {
+            let mut text = term_text(&expr.0);
+            for (operator, term) in expr.1.iter() {
+                let operator = match operator {
+                    ExprGroup1::Pattern1(node) | ExprGroup1::Pattern2(node) => &node.text,
+                };
+                text += format!(" {} {}", operator, term_text(term)).as_str();
+            }
+            return text;
+        }
+
+        fn term_text(term: &Term) -> String {
+            return match term {
+                Term::Pattern1(_, expr, _) => format!("({})", expr_text(expr)),
+                Term::Name(name) => name.0.text.clone(),
+                Term::Number(number) => number.0.text.clone(),
+            };
+        }
+
+        #[test]
+        fn test() {
+            let source = parse_source_typed("let a: int = (1 + b) - 2; a;").unwrap();
+            assert_eq!(source.0.len(), 2);
+
+            match &source.0[0] {
+                Statement::Pattern1(_, name, type_name, _, expr, _) => {
+                    assert_eq!(name.0.text, "a");
+                    let (colon, type_name) = type_name.as_ref().unwrap();
+                    assert_eq!(colon.text, ":");
+                    assert_eq!(type_name.0.text, "int");
+                    assert_eq!(expr_text(expr), "(1 + b) - 2");
+                }
+                _ => panic!(),
+            }
+            match &source.0[1] {
+                Statement::Pattern2(expr, semicolon) => {
+                    assert_eq!(expr_text(expr), "a");
+                    assert_eq!(semicolon.position.start_position, 27);
+                }
+                _ => panic!(),
+            }
+
+            let error = parse_source_typed("let = 1;").err().unwrap();
+            assert_eq!(error.message, r#"expected r"[a-z]+" but found "=""#);
+        }
+
+        mod labels {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            // rules which have the same names as types used by the typed AST
+            crate::bnf_rules_macro::bnf_rules!(
+                #[typed_ast = true]
+
+                source        ::= { option } [ group1 ] [ result ]
+                option        ::= "set" name:vec value:[ terminal_node ] ";"
+                vec           ::= lhs:box op:( "+" | "-" ) rhs:box | box
+                box           ::= r"[a-z]+"
+                terminal_node ::= "=" r"\d+"
+                group1        ::= "unused"
+                result        ::= "end"
+            );
+
+            // the generated parser must not use these types instead of the std ones
+            use typed_ast::*;
+
+            #[test]
+            fn test() {
+                let source = parse_source_typed("set a + b = 1; set c;").unwrap();
+                assert_eq!(source.0.len(), 2);
+
+                let Option {
+                    _0,
+                    name,
+                    value,
+                    _3,
+                } = &source.0[0];
+                assert_eq!(_0.text, "set");
+                match name.as_ref() {
+                    Vec::Pattern1 { lhs, op, rhs } => {
+                        assert_eq!(lhs.0.text, "a");
+                        assert!(matches!(op, VecOp::Pattern1(node) if node.text == "+"));
+                        assert_eq!(rhs.0.text, "b");
+                    }
+                    Vec::Box(_) => panic!(),
+                }
+                assert_eq!(value.as_ref().unwrap().1.text, "1");
+
+                assert!(matches!(source.0[1].name.as_ref(), Vec::Box(node) if node.0.text == "c"));
+                assert!(source.0[1].value.is_none());
+
+                // 'Group1' is taken by the rule 'group1'
+                let _ = Group1(_0.clone());
+
+                let source = parse_source_typed("set a; end").unwrap();
+                assert!(matches!(source.2.as_deref(), Some(Result(node)) if node.text == "end"));
+                let (node, errors) = parse_source_with_errors("set a; end");
+                assert!(node.is_some() && errors.is_empty());
+            }
+        }
+
+        #[test]
+        fn test_errors() {
+            let (errors, _) = super::generate(
+                r#"
+                #[typed_ast = true]
+                source ::= name:"a" name:"b"
+                "#,
+            );
+            assert_eq!(
+                errors,
+                vec!["Field 'name' is defined more than once in the typed AST of this pattern."]
+            );
+        }
+    }
+
+    mod test_semantic_action {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[left("+", "-")]
+            #[left("*")]
+
+            #[type = Vec<i64>]
+            source    ::= { expr ";" } => { $1.into_iter().map(|(value, _)| value).collect() }
+            #[type = i64]
+            expr      ::= expr "+" expr => { $1 + $3 }
+                        | expr "-" expr => { $1 - $3 }
+                        | expr "*" expr => { $1 * $3 }
+                        | "(" expr ")" => { $2 }
+                        | "-" number => { -$2 }
+                        | number
+            #[type = i64]
+            number    ::= r"\d+" => { $1.text.parse().unwrap() }
+        );
+
+        #[test]
+        fn test() {
+            assert_eq!(
+                parse_source_value("1 + 2 * 3; (1 + 2) * 3; 10 - 4 - -3;").unwrap(),
+                vec![7, 9, 9]
+            );
+            assert_eq!(parse_source_value("").unwrap(), Vec::<i64>::new());
+
+            let error = parse_source_value("1 + ;").err().unwrap();
+            assert_eq!(
+                error.message,
+                r#"expected one of "-", "(", r"\d+" but found ";""#
+            );
+        }
+
+        #[test]
+        fn test_errors() {
+            let (errors, _) = super::generate(
+                r#"
+                #[type = i64]
+                source ::= "a" "b"
+                "#,
+            );
+            assert_eq!(
+                errors,
+                vec!["An action is required for this pattern, because the rule has a type."]
+            );
+
+            let (errors, _) = super::generate(r#"source ::= "a" => { $2 }"#);
+            assert_eq!(
+                errors,
+                vec!["Expected '$1' to '$1' for symbols of the pattern."]
+            );
+
+            let (errors, _) = super::generate(r#"source ::= [ "a" => { 1 } ]"#);
+            assert_eq!(
+                errors,
+                vec!["Actions are only available in patterns of named rules."]
+            );
+        }
+
+        mod std_names {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            // the typed AST defines 'Option' and 'Vec', which values of '[ ]' and '{ }' must not use
+            crate::bnf_rules_macro::bnf_rules!(
+                #[typed_ast = true]
+
+                #[type = i64]
+                source ::= { option } [ vec ] => { $1.into_iter().sum::<i64>() + $2.unwrap_or(0) }
+                #[type = i64]
+                option ::= "set" [ r"\d+" ] ";" => { $2.map_or(0, |node| node.text.parse().unwrap()) }
+                #[type = i64]
+                vec    ::= "end" r"\d+" => { $2.text.parse().unwrap() }
+            );
+
+            use typed_ast::*;
+
+            #[test]
+            fn test() {
+                assert_eq!(parse_source_value("set 1; set; set 2; end 3").unwrap(), 6);
+                assert_eq!(parse_source_value("").unwrap(), 0);
+
+                let source = parse_source_typed("set; end 3").unwrap();
+                assert!(matches!(source.1.as_deref(), Some(Vec(_, node)) if node.text == "3"));
+            }
+        }
+    }
+
+    mod test_label {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            source    ::= { statement }
+            statement ::= "let" name:name [ ":" type_name:name ] "=" value:binop ";"
+                        | callee:name "(" [ arg:binop { "," arg:binop } ] ")" ";"
+            binop     ::= lhs:name op:("+" | "-") rhs:name | name
+            name      ::= r"[a-z]+"
+        );
+
+        fn text(node: &ASTNode) -> String {
+            return match node {
+                NonTerminal { children, .. } => {
+                    children.iter().map(text).collect::<Vec<_>>().join(" ")
+                }
+                Terminal { text, .. } => text.clone(),
+                ASTNode::Error { .. } => "<error>".to_string(),
+            };
+        }
+
+        #[test]
+        fn test() {
+            let node = parse_source("let a = b - c; let d: e = f; g(h, i + j);").unwrap();
+            let statements = node.get_children();
+
+            assert_eq!(text(statements[0].child("name").unwrap()), "a");
+            assert!(statements[0].child("type_name").is_none());
+            let value = statements[0].child("value").unwrap();
+            assert_eq!(text(value.child("lhs").unwrap()), "b");
+            assert_eq!(text(value.child("op").unwrap()), "-");
+            assert_eq!(text(value.child("rhs").unwrap()), "c");
+
+            // the position of 'value' does not depend on '[ ]'
+            assert_eq!(text(statements[1].child("type_name").unwrap()), "e");
+            assert_eq!(text(statements[1].child("value").unwrap()), "f");
+            assert_eq!(
+                statements[1].child("value").unwrap().get_label(),
+                Some(&"value".to_string())
+            );
+
+            let args = statements[2].children_named("arg");
+            assert_eq!(
+                args.iter().map(|arg| text(arg)).collect::<Vec<_>>(),
+                ["h", "i + j"]
+            );
+            assert!(statements[2].children_named("lhs").is_empty());
+        }
+
+        #[test]
+        fn test_errors() {
+            let (errors, _) = super::generate(r#"source ::= "a" label: | "b""#);
+            assert_eq!(errors, vec!["A label must be followed by a symbol."]);
+
+            // groups with different labels are not merged
+            let (errors, _) = super::generate(r#"source ::= a:( "x" ) "y" | b:( "x" ) "z""#);
+            assert!(errors.is_empty());
+        }
+    }
+
+    /// Generate a parser without the macro.<br>
+    /// Returns messages of errors and warnings.
+    fn generate(grammar: &str) -> (Vec<String>, Vec<String>) {
+        use crate::bnf_rules_parser::{parse_rules, ParserGenerator};
+        use proc_macro2::{TokenStream, TokenTree};
+
+        let tokens = grammar
+            .parse::<TokenStream>()
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<TokenTree>>();
+
+        let (map, settings) = match parse_rules(&tokens) {
+            Ok(result) => result,
+            Err(error) => return (error.into_iter().map(|e| e.to_string()).collect(), vec![]),
+        };
+        let mut generator = ParserGenerator::new(map, settings);
+        let errors = match generator.generate() {
+            Ok(_) => vec![],
+            Err(error) => error.into_iter().map(|e| e.to_string()).collect(),
+        };
+        let warnings = generator
+            .get_warnings()
+            .iter()
+            .map(|warning| warning.message.clone())
+            .collect();
+
+        return (errors, warnings);
+    }
+
+    mod test_reproducible {
+        use crate::bnf_rules_parser::{parse_rules, ParserGenerator};
+        use proc_macro2::{TokenStream, TokenTree};
+
+        fn generate_code(grammar: &str) -> String {
+            let tokens = grammar
+                .parse::<TokenStream>()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<TokenTree>>();
+
+            let (map, settings) = parse_rules(&tokens).unwrap();
+            return ParserGenerator::new(map, settings).generate().unwrap();
+        }
+
+        #[test]
+        fn test() {
+            let grammar = r#"
+                #[left("+", "-")]
+                #[left("*", "/")]
+
+                source   ::= { statement }
+                statement ::= "let" ident "=" expr ";" | expr ";"
+                expr     ::= expr "+" expr | expr "-" expr
+                           | expr "*" expr | expr "/" expr
+                           | "(" expr ")" | [ "-" ] number | ident
+                number   ::= r"\d+"
+                ident    ::= r"\w+"
+            "#;
+
+            let code = generate_code(grammar);
+            for _ in 0..10 {
+                assert_eq!(generate_code(grammar), code);
+            }
+
+            let dense_code = generate_code(&format!("#[compress_table = false] {}", grammar));
+            assert!(code.len() < dense_code.len());
+
+            // merging states with the same core shrinks the table
+            let pager_code = generate_code(&format!("#[table = pager] {}", grammar));
+            let lalr_code = generate_code(&format!("#[table = lalr] {}", grammar));
+            assert!(pager_code.len() < code.len());
+            assert_eq!(pager_code, lalr_code);
+        }
+    }
+
+    mod test_dense_table {
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[compress_table = false]
+            #[nonassoc("==")]
+            #[left("+")]
+
+            source   ::= expr
+            expr     ::= expr "+" expr | expr "==" expr | r"\d+"
+        );
+
+        #[test]
+        fn test() {
+            assert!(parse_source("1 + 2 == 3").is_ok());
+            assert!(parse_source("1 == 2 == 3").is_err());
+            assert!(parse_source("1 + + 2").is_err());
+        }
+    }
+
+    mod test_default_reductions {
+        // states after a number are merged by LALR(1), but the states after reductions are not
+        mod compressed {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[scan = on_demand]
+                #[table = lalr]
+
+                source   ::= "(" number ")" | "[" number "]"
+                number   ::= r"\d+"
+            );
+        }
+
+        mod dense {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[scan = on_demand]
+                #[table = lalr]
+                #[compress_table = false]
+
+                source   ::= "(" number ")" | "[" number "]"
+                number   ::= r"\d+"
+            );
+        }
+
+        mod default_reductions {
+            mod bnf_rules {
+                pub extern crate bnf_rules_macro;
+                pub extern crate bnf_rules_parser;
+            }
+
+            crate::bnf_rules_macro::bnf_rules!(
+                #[scan = on_demand]
+                #[table = lalr]
+                #[default_reductions = true]
+
+                source   ::= "(" number ")" | "[" number "]"
+                number   ::= r"\d+"
+            );
+        }
+
+        #[test]
+        fn test() {
+            let expected = compressed::parse_source("(1 2").unwrap_err().expected;
+            assert_eq!(expected, vec![r#"")""#, r#""]""#]);
+            assert_eq!(dense::parse_source("(1 2").unwrap_err().expected, expected);
+
+            // the error is detected after reducing 'number'
+            let error = default_reductions::parse_source("(1 2").unwrap_err();
+            assert_eq!(error.expected, vec![r#"")""#]);
+            assert!(default_reductions::parse_source("[1]").is_ok());
+        }
+    }
+
+    mod test_table_mode {
+        use super::generate;
+
+        mod bnf_rules {
+            pub extern crate bnf_rules_macro;
+            pub extern crate bnf_rules_parser;
+        }
+
+        crate::bnf_rules_macro::bnf_rules!(
+            #[table = pager]
+
+            source   ::= "a" e "c" | "a" f "d" | "b" f "c" | "b" e "d" | "(" { source } ")"
+            e        ::= "e"
+            f        ::= "e"
+        );
+
+        #[test]
+        fn test() {
+            assert!(parse_source("(a e c (b e c) b e d)").is_ok());
+            assert!(parse_source("(a e e)").is_err());
+
+            // LR(1), but not LALR(1)
+            let grammar = |mode: &str| {
+                format!(
+                    r#"
+                    #[table = {}]
+                    source ::= "a" e "c" | "a" f "d" | "b" f "c" | "b" e "d"
+                    e      ::= "e"
+                    f      ::= "e"
+                    "#,
+                    mode
+                )
+            };
+            assert!(generate(&grammar("lr1")).0.is_empty());
+            assert!(generate(&grammar("pager")).0.is_empty());
+
+            let (errors, _) = generate(&grammar("lalr"));
+            assert_eq!(errors.len(), 2);
+            assert!(errors[0].starts_with("Reduce(") && errors[0].contains(") Reduce("));
+        }
+    }
+
+    mod test_conflicts {
+        use super::generate;
+
+        #[test]
+        fn test() {
+            let (errors, _) = generate(
+                r#"
+                source ::= expr
+                expr   ::= expr "+" expr | expr "*" expr | r"\d+"
+                "#,
+            );
+            let report = errors.join("\n\n");
+
+            assert_eq!(errors.len(), 4);
+            assert!(report.contains(r#"conflict! | Symbol : "+""#));
+            assert!(report.contains(r#"conflict! | Symbol : "*""#));
+            assert!(report.contains(r#"Reduce item : expr ::= expr "+" expr •"#));
+            assert!(report.contains(r#"Shift item : expr ::= expr • "*" expr"#));
+            assert!(report.contains(r#"Reduce derivation : expr "+" expr • "*""#));
+        }
+
+        #[test]
+        fn test_nonassoc() {
+            // '#[nonassoc]' resolves the shift of "==", but not the other reduction
+            let (errors, _) = generate(
+                r#"
+                #[nonassoc("==")]
+                source ::= expr | equal "==" "x"
+                expr   ::= expr "==" expr | r"\d+"
+                equal  ::= expr "==" expr
+                "#,
+            );
+            assert_eq!(errors.len(), 1);
+            assert!(errors[0].contains(r#"conflict! | Symbol : "==""#));
+            assert!(errors[0].contains(r#"Reduce item : expr ::= expr "==" expr •"#));
+            assert!(errors[0].contains(r#"Reduce item : equal ::= expr "==" expr •"#));
+        }
+
+        #[test]
+        fn test_undefined_symbol() {
+            let (errors, _) = generate(
+                r#"
+                source ::= expr
+                expr   ::= term "+" term
+                "#,
+            );
+
+            assert_eq!(errors, vec!["No symbol found : 'term'"; 2]);
+
+            let (errors, _) = generate(
+                r#"
+                #[sync(";")]
+                source ::= expr
+                expr   ::= r"\d+"
+                "#,
+            );
+            assert_eq!(errors, vec!["Sync symbol is not used in rules."]);
+
+            let (errors, _) = generate(
+                r#"
+                source ::= error
+                error  ::= r"\d+"
+                "#,
+            );
+            assert_eq!(errors, vec!["'error' is reserved for error productions."]);
+        }
+    }
+
+    mod test_lint {
+        use super::generate;
+
+        #[test]
+        fn test() {
+            let (errors, warnings) = generate(
+                r#"
+                source ::= expr [ "-" ]
+                expr   ::= r"\d+"
+                unused ::= expr
+                "#,
+            );
+            assert!(errors.is_empty());
+            assert_eq!(
+                warnings,
+                vec!["Rule 'unused' is unreachable from 'source'."]
+            );
+
+            // '[ ]' around nullable contents always conflicts
+            let (errors, warnings) = generate(
+                r#"
+                source ::= expr [ { "-" } ]
+                expr   ::= r"\d+"
+                "#,
+            );
+            assert_eq!(errors.len(), 1);
+            assert_eq!(
+                warnings,
+                vec!["'[ ]' is useless, because the content can be empty."]
+            );
+
+            let (errors, _) = generate(
+                r#"
+                source ::= expr | list
+                expr   ::= "(" expr ")"
+                list   ::= "[" { expr } "]"
+                "#,
+            );
+            assert_eq!(
+                errors,
+                vec!["Rule 'expr' never terminates, it is infinitely recursive."]
+            );
+
+            // errors are in order of definition
+            for _ in 0..10 {
+                let (errors, _) = generate(
+                    r#"
+                    source ::= a b
+                    a      ::= c | d
+                    b      ::= e
+                    "#,
+                );
+                assert_eq!(
+                    errors,
+                    vec![
+                        "No symbol found : 'c'",
+                        "No symbol found : 'd'",
+                        "No symbol found : 'e'",
+                    ]
+                );
+            }
+
+            let (errors, _) = generate(
+                r#"
+                source ::= expr
+                expr   ::= r"\d+"
+                expr   ::= "0"
+                "#,
+            );
+            assert_eq!(
+                errors,
+                vec![
+                    "Rule 'expr' is defined more than once.",
+                    "'expr' is first defined here.",
+                ]
+            );
+        }
     }
 }